@@ -2,6 +2,7 @@
 extern crate criterion;
 
 use criterion::{Criterion, black_box};
+use std::{collections::VecDeque, sync::{Condvar, Mutex}, time::Instant};
 
 const JOBC: usize = 100000;
 
@@ -18,6 +19,91 @@ fn lagoon_threadpool(threads: usize) {
     pool.join_all().unwrap();
 }
 
+fn lagoon_threadpool_uncaught(threads: usize) {
+    let pool = lagoon::ThreadPool::build()
+        .with_thread_count(threads)
+        .with_catch_panics(false)
+        .finish()
+        .unwrap();
+    for _ in 0..JOBC {
+        pool.run(|| {
+            let _ = black_box(8 + 9);
+        });
+    }
+    pool.join_all().unwrap();
+}
+
+fn lagoon_threadpool_spin(threads: usize) {
+    let pool = lagoon::ThreadPool::build()
+        .with_thread_count(threads)
+        .with_idle_strategy(lagoon::IdleStrategy::SpinThenPark { spins: 100 })
+        .finish()
+        .unwrap();
+    for _ in 0..JOBC {
+        pool.run(|| {
+            let _ = black_box(8 + 9);
+        });
+    }
+    pool.join_all().unwrap();
+}
+
+// A FIFO queue built on a mutex and a condition variable, to compare the built-in queue against one that goes through
+// `JobQueue`
+#[derive(Default)]
+struct MutexQueue {
+    jobs: Mutex<(VecDeque<lagoon::Job>, bool)>,
+    available: Condvar,
+}
+
+impl lagoon::JobQueue for MutexQueue {
+    fn push(&self, job: lagoon::Job) {
+        self.jobs.lock().unwrap().0.push_back(job);
+        self.available.notify_one();
+    }
+
+    fn pop(&self, deadline: Option<Instant>) -> Option<lagoon::Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        loop {
+            match jobs.0.pop_front() {
+                Some(job) => break Some(job),
+                None if jobs.1 => break None,
+                None => match deadline {
+                    Some(deadline) => {
+                        let timeout = deadline.checked_duration_since(Instant::now())?;
+                        jobs = self.available.wait_timeout(jobs, timeout).unwrap().0;
+                    },
+                    None => jobs = self.available.wait(jobs).unwrap(),
+                },
+            }
+        }
+    }
+
+    fn try_pop(&self) -> Option<lagoon::Job> { self.jobs.lock().unwrap().0.pop_front() }
+
+    fn len(&self) -> usize { self.jobs.lock().unwrap().0.len() }
+
+    fn close(&self) {
+        self.jobs.lock().unwrap().1 = true;
+        self.available.notify_all();
+    }
+
+    fn is_closed(&self) -> bool { self.jobs.lock().unwrap().1 }
+}
+
+fn lagoon_threadpool_custom_queue(threads: usize) {
+    let pool = lagoon::ThreadPool::build()
+        .with_thread_count(threads)
+        .with_queue(MutexQueue::default)
+        .finish()
+        .unwrap();
+    for _ in 0..JOBC {
+        pool.run(|| {
+            let _ = black_box(8 + 9);
+        });
+    }
+    pool.join_all().unwrap();
+}
+
 fn threadpool_threadpool(threads: usize) {
     let pool = threadpool::ThreadPool::new(threads);
     for _ in 0..JOBC {
@@ -50,13 +136,69 @@ fn rusty_pool_threadpool(threads: usize) {
     pool.shutdown_join();
 }
 
+#[cfg(feature = "recv")]
+fn lagoon_recv(threads: usize) {
+    let pool = lagoon::ThreadPool::build()
+        .with_thread_count(threads)
+        .finish()
+        .unwrap();
+    let jobs = (0..JOBC as u32)
+        .map(|i| pool.run_recv(move || black_box(i + 9)))
+        .collect::<Vec<_>>();
+    for job in jobs {
+        let _ = black_box(job.join().unwrap());
+    }
+    pool.join_all().unwrap();
+}
+
+#[cfg(feature = "recv")]
+fn lagoon_recv_small(threads: usize) {
+    let pool = lagoon::ThreadPool::build()
+        .with_thread_count(threads)
+        .finish()
+        .unwrap();
+    let jobs = (0..JOBC as u32)
+        .map(|i| pool.run_recv_small(move || black_box(i + 9)))
+        .collect::<Vec<_>>();
+    for job in jobs {
+        let _ = black_box(job.join().unwrap());
+    }
+    pool.join_all().unwrap();
+}
+
+#[cfg(feature = "recv")]
+fn lagoon_recv_into(threads: usize) {
+    let pool = lagoon::ThreadPool::build()
+        .with_thread_count(threads)
+        .finish()
+        .unwrap();
+    let slot = lagoon::ResultSlot::new();
+    for i in 0..JOBC as u32 {
+        pool.run_recv_into(&slot, move || black_box(i + 9));
+        let _ = black_box(slot.join().unwrap());
+    }
+    pool.join_all().unwrap();
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     let threads = num_cpus::get();
     let mut group = c.benchmark_group(format!("Spawning {} trivial tasks", JOBC));
     group.bench_function("lagoon_threadpool", |b| b.iter(|| lagoon_threadpool(threads)));
+    group.bench_function("lagoon_threadpool_uncaught", |b| b.iter(|| lagoon_threadpool_uncaught(threads)));
+    group.bench_function("lagoon_threadpool_spin", |b| b.iter(|| lagoon_threadpool_spin(threads)));
+    group.bench_function("lagoon_threadpool_custom_queue", |b| b.iter(|| lagoon_threadpool_custom_queue(threads)));
     group.bench_function("threadpool_threadpool", |b| b.iter(|| threadpool_threadpool(threads)));
     group.bench_function("uvth_threadpool", |b| b.iter(|| uvth_threadpool(threads)));
     group.bench_function("rusty_pool_threadpool", |b| b.iter(|| rusty_pool_threadpool(threads)));
+    group.finish();
+
+    #[cfg(feature = "recv")]
+    {
+        let mut group = c.benchmark_group(format!("Joining {} u32 results", JOBC));
+        group.bench_function("lagoon_run_recv", |b| b.iter(|| lagoon_recv(threads)));
+        group.bench_function("lagoon_run_recv_small", |b| b.iter(|| lagoon_recv_small(threads)));
+        group.bench_function("lagoon_run_recv_into", |b| b.iter(|| lagoon_recv_into(threads)));
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
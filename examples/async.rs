@@ -0,0 +1,23 @@
+#[cfg(not(feature = "async"))]
+fn main() { panic!("This example requires the `async` feature") }
+
+// Offload CPU-heavy work from async tasks to a lagoon pool without blocking the async runtime
+#[cfg(feature = "async")]
+#[tokio::main]
+async fn main() {
+    let pool = lagoon::ThreadPool::default();
+
+    let tasks = (0..10u64)
+        .map(|i| {
+            let job = pool.run_recv(move || (0..i * 1_000_000).fold(0u64, |acc, x| acc.wrapping_add(x * x)));
+            tokio::spawn(async move {
+                let sum = job.await.unwrap();
+                println!("Job {} finished with {}", i, sum);
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+}
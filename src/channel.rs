@@ -0,0 +1,12 @@
+// The channel backend used for job queues, selected at compile time. Both backends expose the same API for all that we
+// need, so the rest of the crate is written against whichever is re-exported here. If both are enabled, flume wins
+// since crossbeam is enabled by default.
+
+#[cfg(not(any(feature = "channel-crossbeam", feature = "channel-flume")))]
+compile_error!("lagoon requires a channel backend: enable either the `channel-crossbeam` or `channel-flume` feature");
+
+#[cfg(all(feature = "channel-crossbeam", not(feature = "channel-flume")))]
+pub(crate) use crossbeam_channel::{bounded, unbounded, Receiver, RecvTimeoutError, Sender, TryRecvError, TrySendError};
+
+#[cfg(feature = "channel-flume")]
+pub(crate) use flume::{bounded, unbounded, Receiver, RecvTimeoutError, Sender, TryRecvError, TrySendError};
@@ -0,0 +1,144 @@
+use super::*;
+
+use std::sync::{Condvar, Mutex, atomic::AtomicU8};
+
+// Whether a pool is paused, along with a way for threads to wait until it is resumed. This is kept separate from the
+// rest of the shared pool state so that jobs may hold onto it without creating a reference cycle through the queue.
+pub(crate) struct PauseState {
+    paused: AtomicBool,
+    lock: Mutex<()>,
+    resumed: Condvar,
+}
+
+impl PauseState {
+    pub(crate) fn new() -> Self {
+        Self {
+            paused: AtomicBool::new(false),
+            lock: Mutex::new(()),
+            resumed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn is_paused(&self) -> bool { self.paused.load(Ordering::Acquire) }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        // Take the lock to ensure that threads that have just checked the flag are waiting before we notify them
+        let _lock = self.lock.lock().unwrap();
+        self.paused.store(paused, Ordering::Release);
+        if !paused {
+            self.resumed.notify_all();
+        }
+    }
+
+    // Block the current thread for as long as the pool is paused
+    pub(crate) fn wait_while_paused(&self) {
+        if self.is_paused() {
+            let mut lock = self.lock.lock().unwrap();
+            while self.is_paused() {
+                lock = self.resumed.wait(lock).unwrap();
+            }
+        }
+    }
+}
+
+/// A token that may be used to request the cancellation of a job. It may be created with [`ThreadPool::run_ctx`].
+///
+/// Cancellation is cooperative: a job that has already started will only stop if it checks its [`JobContext`]. A job
+/// that is cancelled before it starts will not be run at all. All clones of a token refer to the same job.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    flags: Arc<JobFlags>,
+}
+
+#[derive(Debug, Default)]
+struct JobFlags {
+    cancelled: AtomicBool,
+    // The progress of the job, as one of the `JOB_*` states. This is only tracked for jobs with handles.
+    state: AtomicU8,
+}
+
+pub(crate) const JOB_QUEUED: u8 = 0;
+#[cfg_attr(not(feature = "recv"), allow(dead_code))]
+pub(crate) const JOB_RUNNING: u8 = 1;
+#[cfg_attr(not(feature = "recv"), allow(dead_code))]
+pub(crate) const JOB_FINISHED: u8 = 2;
+#[cfg_attr(not(feature = "recv"), allow(dead_code))]
+pub(crate) const JOB_PANICKED: u8 = 3;
+pub(crate) const JOB_CANCELLED: u8 = 4;
+
+impl CancelToken {
+    /// Request that the job associated with this token be cancelled.
+    pub fn cancel(&self) {
+        self.flags.cancelled.store(true, Ordering::Release);
+        self.transition(JOB_QUEUED, JOB_CANCELLED);
+    }
+
+    /// Returns whether cancellation of the job associated with this token has been requested.
+    pub fn is_cancelled(&self) -> bool { self.flags.cancelled.load(Ordering::Acquire) }
+
+    // Move the job from one state to another, returning whether it was in the first state
+    pub(crate) fn transition(&self, from: u8, to: u8) -> bool {
+        self.flags.state.compare_exchange(from, to, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }
+
+    #[cfg_attr(not(feature = "recv"), allow(dead_code))]
+    pub(crate) fn state(&self) -> u8 { self.flags.state.load(Ordering::Acquire) }
+
+    #[cfg_attr(not(feature = "recv"), allow(dead_code))]
+    pub(crate) fn set_state(&self, state: u8) { self.flags.state.store(state, Ordering::Release) }
+}
+
+/// Information about, and control over, a running job. It is passed to jobs enqueued with [`ThreadPool::run_ctx`].
+///
+/// Long-running jobs should call [`JobContext::yield_now`] periodically so that they may be paused and cancelled.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let token = pool.run_ctx(|ctx| {
+///     loop {
+///         // Stop as soon as we're cancelled, and wait while the pool is paused
+///         if ctx.yield_now().is_err() {
+///             break;
+///         }
+///         # std::thread::sleep(std::time::Duration::from_millis(1));
+///     }
+/// });
+///
+/// token.cancel();
+/// pool.join_all().unwrap();
+/// ```
+pub struct JobContext<'a> {
+    pause: &'a PauseState,
+    token: &'a CancelToken,
+    worker: Option<usize>,
+}
+
+impl<'a> JobContext<'a> {
+    pub(crate) fn new(pause: &'a PauseState, token: &'a CancelToken, pool_id: usize) -> Self {
+        Self {
+            pause,
+            token,
+            worker: worker::current().filter(|(id, _)| *id == pool_id).and_then(|(_, idx)| idx),
+        }
+    }
+
+    /// Returns the index of the worker executing this job, or `None` if the job is not being executed by one of the
+    /// pool's workers (for example, because a thread waiting on a scope is executing it).
+    pub fn worker_index(&self) -> Option<usize> { self.worker }
+
+    /// Returns the cancellation token associated with this job.
+    pub fn token(&self) -> &CancelToken { self.token }
+
+    /// Returns whether cancellation of this job has been requested.
+    pub fn is_cancelled(&self) -> bool { self.token.is_cancelled() }
+
+    /// A cooperative yield point for long-running jobs.
+    ///
+    /// If the pool is paused, this blocks until it is resumed. Returns [`Error::Cancelled`] if cancellation of this job
+    /// has been requested, in which case the job should stop as soon as possible.
+    pub fn yield_now(&self) -> Result<(), Error> {
+        self.pause.wait_while_paused();
+        if self.is_cancelled() { Err(Error::Cancelled) } else { Ok(()) }
+    }
+}
@@ -0,0 +1,115 @@
+//! A C interface to [`ThreadPool`], allowing hosts written in other languages to use the pool.
+//!
+//! Pools are referred to by opaque pointers, and jobs are C function pointers paired with a `void*` context that is
+//! passed to the function when the job is executed. The equivalent C declarations are:
+//!
+//! ```c
+//! typedef struct lagoon_pool lagoon_pool;
+//!
+//! lagoon_pool *lagoon_pool_new(size_t threads);
+//! int lagoon_pool_run(const lagoon_pool *pool, void (*f)(void *ctx), void *ctx);
+//! int lagoon_pool_join_all(lagoon_pool *pool);
+//! ```
+//!
+//! # Ownership and thread safety
+//!
+//! - A pool is owned by the host from [`lagoon_pool_new`] until it is given back to [`lagoon_pool_join_all`], which
+//!   frees it. It may be used from any number of threads at once in the meantime.
+//! - The context of a job is never read, written or freed by the pool: it is only passed to the job's function, once,
+//!   on one of the pool's threads. The host is responsible for keeping whatever it points to alive (and freeing it,
+//!   if necessary) until the function has been called, and for synchronising any access to it that is shared with
+//!   other threads.
+//! - Job functions must not unwind (for example, by throwing a C++ exception), since unwinding across the FFI boundary
+//!   is undefined behaviour.
+//!
+//! ```
+//! use std::{ffi::c_void, sync::atomic::{AtomicUsize, Ordering}};
+//! use lagoon::ffi::*;
+//!
+//! extern "C" fn count(ctx: *mut c_void) {
+//!     // Safety: the context is always a pointer to `COUNT`, which lives forever
+//!     unsafe { &*(ctx as *const AtomicUsize) }.fetch_add(1, Ordering::Relaxed);
+//! }
+//!
+//! static COUNT: AtomicUsize = AtomicUsize::new(0);
+//!
+//! let pool = lagoon_pool_new(0);
+//! assert!(!pool.is_null());
+//! for _ in 0..10 {
+//!     // Safety: the pool has not yet been joined
+//!     assert_eq!(unsafe { lagoon_pool_run(pool, Some(count), &COUNT as *const _ as *mut c_void) }, 0);
+//! }
+//! // Safety: the pool is not used after this point
+//! assert_eq!(unsafe { lagoon_pool_join_all(pool) }, 0);
+//! assert_eq!(COUNT.load(Ordering::Relaxed), 10);
+//! ```
+
+use super::*;
+
+use std::{ffi::c_void, os::raw::c_int};
+
+/// The function of a job enqueued with [`lagoon_pool_run`].
+pub type JobFn = unsafe extern "C" fn(ctx: *mut c_void);
+
+// The context of a job, which the host promises may be sent to the pool's threads
+struct Ctx(*mut c_void);
+
+// Safety: see the thread safety contract in the module documentation
+unsafe impl Send for Ctx {}
+
+/// Create a new pool with the given number of threads, or with the default number of threads (see
+/// [`ThreadPool::default`]) if `threads` is `0`. Returns a null pointer if the pool could not be created.
+///
+/// The pool must eventually be given to [`lagoon_pool_join_all`], which frees it.
+#[no_mangle]
+pub extern "C" fn lagoon_pool_new(threads: usize) -> *mut ThreadPool {
+    let builder = match threads {
+        0 => ThreadPool::build(),
+        n => ThreadPool::build().with_thread_count(n),
+    };
+    match builder.finish() {
+        Ok(pool) => Box::into_raw(Box::new(pool)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Enqueue a job that calls `f` with `ctx` when a thread is free to do so. Returns `0` if the job was enqueued, and
+/// `-1` if it was not (because `f` is null, or for any of the reasons that [`ThreadPool::try_run`] fails).
+///
+/// # Safety
+///
+/// `pool` must have been returned by [`lagoon_pool_new`] and not yet given to [`lagoon_pool_join_all`]. `f` and `ctx`
+/// must uphold the contract described in the [module documentation](self).
+#[no_mangle]
+pub unsafe extern "C" fn lagoon_pool_run(pool: *const ThreadPool, f: Option<JobFn>, ctx: *mut c_void) -> c_int {
+    let (pool, f) = match (pool.as_ref(), f) {
+        (Some(pool), Some(f)) => (pool, f),
+        _ => return -1,
+    };
+    let ctx = Ctx(ctx);
+    // Safety: the host guarantees that `f` may be called with `ctx` on any thread
+    match pool.try_run(move || f(ctx.0)) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Wait for every job enqueued on the pool to finish, then stop its threads and free it (see
+/// [`ThreadPool::join_all`]). Returns `0` once the pool has been joined, or `-1` if `pool` is null or a worker thread
+/// panicked.
+///
+/// # Safety
+///
+/// `pool` must have been returned by [`lagoon_pool_new`] and not yet given to this function. It must not be used
+/// again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn lagoon_pool_join_all(pool: *mut ThreadPool) -> c_int {
+    if pool.is_null() {
+        return -1;
+    }
+    // Safety: the pool was leaked from a box by `lagoon_pool_new`, and the caller guarantees that it is unused
+    match Box::from_raw(pool).join_all() {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
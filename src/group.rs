@@ -0,0 +1,86 @@
+use super::*;
+
+use std::sync::{Condvar, Mutex, atomic::{AtomicUsize, Ordering}};
+
+struct GroupState {
+    outstanding: AtomicUsize,
+    lock: Mutex<()>,
+    finished: Condvar,
+}
+
+// Decrements the outstanding job count when dropped, even if the job panicked
+struct GroupGuard(Arc<GroupState>);
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        if self.0.outstanding.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Take the lock to ensure that waiters that have just checked the count are parked before we notify them
+            let _lock = self.0.lock.lock().unwrap();
+            self.0.finished.notify_all();
+        }
+    }
+}
+
+/// A logical group of jobs that may be waited upon independently of other work on the pool. It may be created with
+/// [`ThreadPool::group`].
+///
+/// Unlike a scope, jobs in a group must be `'static` and the group may be freely cloned
+/// and passed around. All clones refer to the same group.
+///
+/// ```
+/// use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+///
+/// let pool = lagoon::ThreadPool::default();
+/// let counter = Arc::new(AtomicUsize::new(0));
+///
+/// let group = pool.group();
+/// for _ in 0..10 {
+///     let counter = counter.clone();
+///     group.run(move || { counter.fetch_add(1, Ordering::Relaxed); });
+/// }
+///
+/// // Wait for every job in the group to finish
+/// group.wait();
+/// assert_eq!(counter.load(Ordering::Relaxed), 10);
+/// ```
+#[derive(Clone)]
+pub struct JobGroup<'pool> {
+    pool: &'pool ThreadPool,
+    state: Arc<GroupState>,
+}
+
+impl<'pool> JobGroup<'pool> {
+    pub(crate) fn new(pool: &'pool ThreadPool) -> Self {
+        Self {
+            pool,
+            state: Arc::new(GroupState {
+                outstanding: AtomicUsize::new(0),
+                lock: Mutex::new(()),
+                finished: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Enqueue a function to be executed as a job in this group when a thread is free to do so.
+    pub fn run<F: FnOnce() + Send + 'static>(&self, f: F) {
+        self.state.outstanding.fetch_add(1, Ordering::AcqRel);
+        let guard = GroupGuard(self.state.clone());
+        self.pool.run(move || {
+            let _guard = guard;
+            f();
+        });
+    }
+
+    /// Returns the number of jobs in this group that have not yet finished.
+    pub fn outstanding(&self) -> usize { self.state.outstanding.load(Ordering::Acquire) }
+
+    /// Block the current thread until every job in this group has finished, including those that panicked.
+    ///
+    /// Jobs spawned into the group while waiting will also be waited upon.
+    pub fn wait(&self) {
+        let mut lock = self.state.lock.lock().unwrap();
+        while self.state.outstanding.load(Ordering::Acquire) > 0 {
+            lock = self.state.finished.wait(lock).unwrap();
+        }
+    }
+}
@@ -6,21 +6,25 @@
 //! - **Job handles**: Receive the result of a job when it finishes, or wait on it to finish!
 //! - **Global pool**: A pay-for-what-you-use global thread pool that avoids dependencies fighting over resources!
 //! - **Customise thread attributes**: Specify thread name, stack size, etc.
+//! - **Async support for job waiting**: Use the thread pool in an async context, with the `async` feature!
 //!
-//! ```ignore
+//! ```
+//! # #[cfg(feature = "recv")] {
 //! let pool = lagoon::ThreadPool::default();
 //!
 //! // Spawn some jobs that notify us when they're finished
-//! let jobs = (0..10)
+//! let mut jobs = (0..10)
 //!     .map(|i| pool.run_recv(move || {
 //!         println!("Hello! i = {}", i);
+//!         i * i
 //!     }))
-//!     .collect::<Vec<_>>();
+//!     .collect::<lagoon::JobSet<_>>();
 //!
-//! // Wait for all jobs to finish
-//! for job in jobs {
-//!     job.join().unwrap();
+//! // Wait for all jobs to finish, taking their results as they do
+//! for res in jobs.as_completed() {
+//!     println!("Finished! i * i = {}", res.unwrap());
 //! }
+//! # }
 //! ```
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
@@ -30,22 +34,73 @@
 mod scope;
 #[cfg(feature = "recv")]
 mod recv;
+mod group;
+mod channel;
+mod queue;
+mod context;
+mod unwind;
+mod pinned;
+mod strand;
+mod par;
+#[cfg(any(
+    feature = "priority",
+    all(unix, feature = "realtime"),
+    feature = "qos",
+    feature = "ioprio",
+    feature = "numa",
+    feature = "affinity",
+))]
+mod os;
+mod worker;
+#[cfg(feature = "ffi")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ffi")))]
+pub mod ffi;
 
 #[cfg(feature = "scope")]
 #[cfg_attr(docsrs, doc(cfg(feature = "scope")))]
-pub use scope::Scope;
+pub use scope::{Scope, CollectScope, ContextScope, LocalScope, TryScope};
 #[cfg(feature = "recv")]
 #[cfg_attr(docsrs, doc(cfg(feature = "recv")))]
-pub use recv::JobHandle;
+pub use recv::{
+    join_all, join_all_results, join_any, select, select_timeout, AbortHandle, AsCompleted, BoxedJobHandle, JobHandle,
+    JobSet, JobStatus, JoinTimeout, MappedJobHandle, ResultSlot, SharedJobHandle, SmallJobHandle, TryJoinError,
+};
+pub use group::JobGroup;
+pub use queue::{FullQueuePolicy, JobQueue, Scheduling};
+pub use context::{CancelToken, JobContext};
+pub use unwind::{Panic, PanicPolicy, PanicStrategy};
+pub use pinned::PinnedWorker;
+pub use strand::Strand;
+pub use par::ParMap;
+pub use worker::IdleStrategy;
+#[cfg(feature = "rand")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rand")))]
+pub use worker::WorkerRng;
+#[cfg(feature = "affinity")]
+#[cfg_attr(docsrs, doc(cfg(feature = "affinity")))]
+pub use os::AffinityStrategy;
+#[cfg(feature = "priority")]
+#[cfg_attr(docsrs, doc(cfg(feature = "priority")))]
+pub use os::Priority;
+#[cfg(all(unix, feature = "realtime"))]
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "realtime"))))]
+pub use os::RtPolicy;
+#[cfg(feature = "qos")]
+#[cfg_attr(docsrs, doc(cfg(feature = "qos")))]
+pub use os::QosClass;
+#[cfg(feature = "ioprio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ioprio")))]
+pub use os::IoPriority;
 
 use std::{
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
+    sync::{Arc, Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}},
     error,
     fmt,
     io,
 };
-// use flume::{Sender, unbounded};
-use crossbeam_channel::{unbounded, Sender};
+use channel::{bounded, unbounded, Receiver, Sender};
 
 /// Attempt to determine the available concurrency of the host system.
 ///
@@ -67,48 +122,653 @@ pub fn available_concurrency() -> Option<usize> {
     std::thread::available_concurrency().map(|n| n.get())
 }
 
+/// Attempt to determine the number of physical CPU cores available to the program.
+///
+/// On machines with simultaneous multithreading (such as Intel's Hyper-Threading), this is usually smaller than
+/// [`available_concurrency`]. If the `num_cpus` feature is enabled (it is by default), the
+/// [`num_cpus`](https://crates.io/crates/num_cpus) crate will be used to determine this value. Otherwise, physical
+/// cores cannot be distinguished and this function falls back to [`available_concurrency`].
+#[cfg(feature = "num_cpus")]
+pub fn available_physical_concurrency() -> Option<usize> {
+    Some(num_cpus::get_physical())
+}
+
+/// Attempt to determine the number of physical CPU cores available to the program.
+///
+/// On machines with simultaneous multithreading (such as Intel's Hyper-Threading), this is usually smaller than
+/// [`available_concurrency`]. If the `num_cpus` feature is enabled (it is by default), the
+/// [`num_cpus`](https://crates.io/crates/num_cpus) crate will be used to determine this value. Otherwise, physical
+/// cores cannot be distinguished and this function falls back to [`available_concurrency`].
+#[cfg(not(feature = "num_cpus"))]
+pub fn available_physical_concurrency() -> Option<usize> {
+    available_concurrency()
+}
+
+// The maximum number of threads assumed where the system's limit can't be detected. Few systems can run more threads
+// than this, and it keeps absurd thread counts from being used to size allocations.
+const FALLBACK_THREAD_LIMIT: usize = 1 << 16;
+
+// Determine the maximum number of threads that the system allows, falling back to a fixed limit where it can't be
+// detected
+fn system_thread_limit() -> usize {
+    #[cfg(target_os = "linux")]
+    if let Some(limit) = std::fs::read_to_string("/proc/sys/kernel/threads-max")
+        .ok()
+        .and_then(|limit| limit.trim().parse().ok())
+    {
+        return limit;
+    }
+    FALLBACK_THREAD_LIMIT
+}
+
+/// Returns the index of the pool worker running on the current thread, or `None` if the current thread is not a worker.
+///
+/// The index ranges from `0` to the thread count of the worker's pool, making it suitable for indexing per-worker data
+/// structures without hashing thread IDs.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::build().with_thread_count(4).finish().unwrap();
+///
+/// assert_eq!(lagoon::current_worker_index(), None);
+/// pool.run(|| assert!(lagoon::current_worker_index().unwrap() < 4));
+/// # pool.join_all().unwrap();
+/// ```
+pub fn current_worker_index() -> Option<usize> {
+    worker::current().and_then(|(_, idx)| idx)
+}
+
+/// Returns information about the job running on the current thread, or `None` if the current thread is not running a
+/// job.
+///
+/// This is useful for identifying the job that panicked from within a handler given to
+/// [`ThreadPoolBuilder::on_panic`], which is invoked on the thread that executed the job.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::build()
+///     .on_panic(|_| {
+///         let job = lagoon::current_job().unwrap();
+///         eprintln!("Job {:?} panicked", job.name);
+///     })
+///     .finish()
+///     .unwrap();
+///
+/// assert!(lagoon::current_job().is_none());
+/// pool.run_named("load-config", || panic!("file not found"));
+/// # pool.join_all().unwrap();
+/// ```
+pub fn current_job() -> Option<JobInfo> { worker::current_job() }
+
+/// Returns the approximate number of bytes left on the stack of the pool worker running on the current thread, or
+/// `None` if the current thread is not a worker of a pool configured with
+/// [`ThreadPoolBuilder::with_thread_stack_size`].
+///
+/// This allows recursive jobs to stop recursing (or to move the rest of their work to another job) before they
+/// overflow the stack, which aborts the process. The estimate assumes that the worker was spawned with the configured
+/// stack size, which may not be the case for workers spawned by [`ThreadPoolBuilder::with_spawner`].
+///
+/// ```
+/// fn sum(xs: &[u64]) -> u64 {
+///     // Leave plenty of room for each level of recursion
+///     if xs.len() <= 1 || lagoon::remaining_stack().is_some_and(|left| left < 64 * 1024) {
+///         xs.iter().sum()
+///     } else {
+///         let (l, r) = xs.split_at(xs.len() / 2);
+///         sum(l) + sum(r)
+///     }
+/// }
+///
+/// let pool = lagoon::ThreadPool::build().with_thread_stack_size(1024 * 1024).finish().unwrap();
+///
+/// assert_eq!(lagoon::remaining_stack(), None);
+/// pool.run(|| assert_eq!(sum(&(0..1000).collect::<Vec<_>>()), 499500));
+/// # pool.join_all().unwrap();
+/// ```
+pub fn remaining_stack() -> Option<usize> { worker::remaining_stack() }
+
+/// Returns the random number generator of the worker running on the current thread, or `None` if the current thread is
+/// not a worker of a pool configured with [`ThreadPoolBuilder::with_rng_seed`].
+///
+/// The generator is owned by the worker and lives for as long as it does, so its sequence continues from one job to
+/// the next.
+#[cfg(feature = "rand")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rand")))]
+pub fn worker_rng() -> Option<WorkerRng> { worker::rng() }
+
 /// An error that may be produced when creating a [`ThreadPool`].
 #[derive(Debug)]
 pub enum Error {
-    /// An IO error occurred when attempting to spawn a thread.
+    /// An IO error occurred.
     Io(io::Error),
+    /// An error occurred when attempting to spawn a thread.
+    Spawn {
+        /// The number of threads that had been spawned successfully before the error occurred. When returned by
+        /// [`ThreadPoolBuilder::finish`], these threads have already been stopped and joined.
+        spawned: usize,
+        /// The error that occurred.
+        error: io::Error,
+    },
     /// The thread pool has no threads.
     NoThreads,
-    /// A timeout occurred when attempting to join a job.
+    /// The thread pool was configured with more threads than the system allows a process to have.
+    TooManyThreads {
+        /// The number of threads that the pool was configured with.
+        requested: usize,
+        /// The maximum number of threads allowed by the system, or the fixed limit assumed where it can't be detected.
+        limit: usize,
+    },
+    /// A timeout occurred when attempting to join a job, or when shutting down a pool with
+    /// [`ShutdownMode::DrainTimeout`].
     Timeout,
+    /// The thread pool has been closed and is no longer accepting jobs.
+    Closed,
+    /// The job was cancelled.
+    Cancelled,
+    /// The job was skipped because its deadline passed before it could start.
+    Skipped,
+    /// The job was dropped without being executed, for example because it was evicted from a full queue by
+    /// [`FullQueuePolicy::DropOldest`].
+    Dropped,
+    /// The queue was full, and the pool uses [`FullQueuePolicy::Fail`].
+    QueueFull,
+    /// The job panicked. This is only returned when the pool uses [`PanicPolicy::Propagate`].
+    Panicked(Panic),
+    /// The job panicked, but the pool does not keep panic payloads (it uses [`PanicPolicy::Catch`]). Use
+    /// [`PanicPolicy::Propagate`] to receive the payload as [`Error::Panicked`] instead.
+    JobPanicked,
+    /// The [`ThreadPoolBuilder`] was given options that contradict one another.
+    InvalidConfig(&'static str),
+    /// The thread name given to [`ThreadPoolBuilder::with_thread_name`] was empty or contained a NUL byte.
+    InvalidThreadName(String),
+    /// The stack size given to [`ThreadPoolBuilder::with_thread_stack_size`] was smaller than
+    /// [`ThreadPoolBuilder::MIN_STACK_SIZE`].
+    StackSizeTooSmall {
+        /// The stack size that the pool was configured with.
+        size: usize,
+        /// The minimum stack size.
+        min: usize,
+    },
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::Io(err) => write!(f, "{}", err),
+            Self::Spawn { spawned, error } => {
+                write!(f, "failed to spawn a thread after spawning {} threads: {}", spawned, error)
+            },
             Self::NoThreads => write!(f, "thread pool has no threads"),
+            Self::TooManyThreads { requested, limit } => {
+                write!(f, "thread pool has {} threads, but the system limit is {}", requested, limit)
+            },
             Self::Timeout => write!(f, "a timeout occurred"),
+            Self::Closed => write!(f, "thread pool is closed"),
+            Self::Cancelled => write!(f, "job was cancelled"),
+            Self::Skipped => write!(f, "job was skipped because its deadline passed"),
+            Self::Dropped => write!(f, "job was dropped without being executed"),
+            Self::QueueFull => write!(f, "thread pool queue is full"),
+            Self::Panicked(panic) => write!(f, "job panicked: {}", panic),
+            Self::JobPanicked => write!(f, "job panicked"),
+            Self::InvalidConfig(msg) => write!(f, "invalid thread pool configuration: {}", msg),
+            Self::InvalidThreadName(name) => write!(f, "invalid thread name {:?}", name),
+            Self::StackSizeTooSmall { size, min } => {
+                write!(f, "thread stack size of {} bytes is smaller than the minimum of {} bytes", size, min)
+            },
         }
     }
 }
 
 impl error::Error for Error {}
 
-struct Job {
+/// A job that has been enqueued on a [`ThreadPool`], as stored by a [`JobQueue`].
+pub struct Job {
+    id: usize,
+    name: Option<Arc<str>>,
     f: Box<dyn FnOnce() + Send>,
 }
 
+impl Job {
+    /// Returns the identifier of the job, which is unique within its pool and assigned in the order that jobs were
+    /// enqueued. See [`JobInfo::id`].
+    pub fn id(&self) -> usize { self.id }
+
+    /// Returns the name of the job, if it was enqueued with [`ThreadPool::run_named`].
+    pub fn name(&self) -> Option<&str> { self.name.as_deref() }
+}
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Job").field("id", &self.id).field("name", &self.name).finish_non_exhaustive()
+    }
+}
+
+// State shared between a pool and its workers
+struct Shared {
+    config: ThreadPoolBuilder,
+    resolved: PoolConfig,
+    thread_count: usize,
+    rx: queue::QueueReceiver,
+    handles: Mutex<Vec<JoinHandle<()>>>,
+    // Held while spawning workers, to avoid multiple threads spawning the same workers
+    spawn_lock: Mutex<()>,
+    // The number of workers that have been spawned (workers are spawned in index order)
+    spawned: AtomicUsize,
+    threads_recycled: AtomicUsize,
+    // The number of workers waiting for a job
+    idle: AtomicUsize,
+    // The number of jobs that have been enqueued but have not yet finished, and the number of those that are executing
+    outstanding: AtomicUsize,
+    active: AtomicUsize,
+    // Notified when the number of outstanding jobs drops to zero
+    quiescent_lock: Mutex<()>,
+    quiescent: std::sync::Condvar,
+    panicked: AtomicUsize,
+    next_job_id: AtomicUsize,
+    // Whether the queue was at or above the high water mark when a job was last enqueued
+    above_high_water: AtomicBool,
+    closed: AtomicBool,
+    pause: Arc<context::PauseState>,
+    // Whether each worker has finished warming up, if the pool was configured with a warmup
+    warmed_up: Mutex<Vec<bool>>,
+    warmed_up_reported: std::sync::Condvar,
+    // The outcome of applying the realtime policy on each worker, once it has been reported
+    #[cfg(all(unix, feature = "realtime"))]
+    realtime_results: Mutex<Vec<Option<io::Result<()>>>>,
+    #[cfg(all(unix, feature = "realtime"))]
+    realtime_reported: std::sync::Condvar,
+    // The number of workers that have successfully applied the configured IO priority
+    #[cfg(feature = "ioprio")]
+    io_priority_applied: AtomicUsize,
+    // The NUMA nodes that workers are placed on (cycling through them by worker index), with the CPUs of each, and the
+    // node that each worker was successfully placed on
+    #[cfg(feature = "numa")]
+    numa_nodes: Vec<(usize, Vec<usize>)>,
+    #[cfg(feature = "numa")]
+    numa_placed: Mutex<Vec<Option<usize>>>,
+    // The cores that workers are pinned to (cycling through them by worker index), either as given to
+    // `ThreadPoolBuilder::with_core_affinity` or as computed from an affinity strategy
+    #[cfg(feature = "affinity")]
+    core_affinity: Vec<usize>,
+    // The number of jobs that each worker has executed
+    #[cfg(feature = "metrics")]
+    jobs_executed: Vec<AtomicUsize>,
+    // Wakes the temporary workers of `ThreadPool::scoped_with_threads`
+    #[cfg(feature = "scope")]
+    temporaries: worker::Temporaries,
+}
+
+impl Shared {
+    // Record that an outstanding job has finished, or will never run
+    fn finish_job(&self) {
+        if self.outstanding.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Take the lock to ensure that waiters that have just checked the count are parked before we notify them
+            let _lock = self.quiescent_lock.lock().unwrap();
+            self.quiescent.notify_all();
+        }
+    }
+
+    fn add_handles(&self, new: impl IntoIterator<Item = JoinHandle<()>>) {
+        let mut handles = self.handles.lock().unwrap();
+        // Threads that have been recycled away have no need to be joined later
+        handles.retain(|handle| !handle.is_finished());
+        handles.extend(new);
+    }
+}
+
+/// The attributes that a [`ThreadPool`] would give one of its worker threads, passed to a custom spawner given to
+/// [`ThreadPoolBuilder::with_spawner`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ThreadConfig {
+    /// The index of the worker, from `0` to the number of threads in the pool. Temporary workers (see
+    /// [`ThreadConfig::temporary`]) are given indices at or above the number of threads in the pool instead.
+    pub index: usize,
+    /// The name of the thread, or `None` for the default name.
+    pub name: Option<String>,
+    /// The stack size of the thread, or `None` for the default stack size.
+    pub stack_size: Option<usize>,
+    /// Whether the thread is a temporary worker, spawned by [`ThreadPool::scoped_with_threads`] for the duration of a
+    /// scope rather than for the lifetime of the pool.
+    pub temporary: bool,
+}
+
+impl ThreadConfig {
+    /// Returns a [`std::thread::Builder`] configured with these attributes, as used by the default spawner.
+    pub fn builder(&self) -> thread::Builder {
+        let builder = thread::Builder::new();
+        let builder = match self.name.clone() {
+            Some(name) => builder.name(name),
+            None => builder,
+        };
+        match self.stack_size {
+            Some(size) => builder.stack_size(size),
+            None => builder,
+        }
+    }
+}
+
+/// The configuration that a [`ThreadPool`] ended up with, after options like
+/// [`ThreadPoolBuilder::with_reserved_cores`] and [`ThreadPoolBuilder::with_env_override`] have been resolved. It may
+/// be retrieved with [`ThreadPool::config`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct PoolConfig {
+    /// The name of the pool, if any.
+    pub pool_name: Option<String>,
+    /// The number of threads in the pool.
+    pub thread_count: usize,
+    /// The name given to the pool's threads, if any.
+    pub thread_name: Option<String>,
+    /// The stack size of the pool's threads, if one was specified.
+    pub thread_stack_size: Option<usize>,
+    /// The order in which the pool executes jobs.
+    pub scheduling: Scheduling,
+    /// What the pool's workers do while waiting for jobs.
+    pub idle_strategy: IdleStrategy,
+    /// The maximum number of jobs that may be queued at once, if the queue is bounded.
+    pub queue_capacity: Option<usize>,
+    /// What the pool does when one of its jobs panics.
+    pub panic_policy: PanicPolicy,
+    /// When the pool spawns its threads.
+    pub spawn_mode: SpawnMode,
+    /// Whether the pool runs in deterministic mode (see [`ThreadPoolBuilder::with_deterministic`]).
+    pub deterministic: bool,
+}
+
+/// Information about a job that is passed to [`ThreadPoolBuilder::on_job_start`] and
+/// [`ThreadPoolBuilder::on_job_end`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct JobInfo {
+    /// The index of the worker executing the job, or `None` if the job is being executed by a thread that is not one
+    /// of the pool's workers (see [`ThreadPool::run_or_inline`]).
+    pub worker: Option<usize>,
+    /// An identifier for the job that is unique within its pool, assigned in the order that jobs were enqueued.
+    pub id: usize,
+    /// The name of the job, if it was enqueued with [`ThreadPool::run_named`].
+    pub name: Option<Arc<str>>,
+}
+
+/// The outcome of a job, passed to [`ThreadPoolBuilder::on_job_end`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum JobOutcome {
+    /// The job returned normally.
+    Completed,
+    /// The job panicked.
+    Panicked,
+}
+
+/// A rule that derives the thread count of a [`ThreadPool`] from the number of hardware threads, used by the presets
+/// [`ThreadPoolBuilder::for_compute`] and [`ThreadPoolBuilder::for_blocking`].
+///
+/// ```
+/// use lagoon::{ThreadCountPreset, ThreadPoolBuilder};
+///
+/// assert_eq!(ThreadCountPreset::Compute.thread_count(8), 7);
+/// assert_eq!(ThreadCountPreset::Compute.thread_count(1), 1);
+/// assert_eq!(ThreadCountPreset::Blocking.thread_count(8), 8 * ThreadPoolBuilder::BLOCKING_THREADS_PER_CORE);
+/// assert_eq!(ThreadCountPreset::Blocking.thread_count(1), ThreadPoolBuilder::BLOCKING_THREADS_PER_CORE);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ThreadCountPreset {
+    /// One thread per hardware thread minus one, with a minimum of one thread.
+    Compute,
+    /// [`ThreadPoolBuilder::BLOCKING_THREADS_PER_CORE`] threads per hardware thread.
+    Blocking,
+}
+
+impl ThreadCountPreset {
+    /// Returns the thread count that this rule gives for the given number of hardware threads.
+    pub fn thread_count(self, cores: usize) -> usize {
+        match self {
+            Self::Compute => cores.saturating_sub(1).max(1),
+            Self::Blocking => cores.saturating_mul(ThreadPoolBuilder::BLOCKING_THREADS_PER_CORE).max(1),
+        }
+    }
+}
+
+/// When a [`ThreadPool`] spawns its threads, configured with [`ThreadPoolBuilder::with_spawn_mode`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SpawnMode {
+    /// Spawn every thread in [`ThreadPoolBuilder::finish`]. This is the default.
+    #[default]
+    Eager,
+    /// Spawn every thread when the first job is enqueued (or when [`ThreadPool::ready`] is called). This is what
+    /// [`ThreadPoolBuilder::spawn_lazily`] selects.
+    OnFirstJob,
+    /// Spawn threads one at a time as jobs are enqueued, whenever none of the threads spawned so far are idle, until
+    /// the configured thread count is reached. This is what [`ThreadPoolBuilder::with_lazy_spawn`] selects.
+    OnDemand,
+}
+
+/// How [`ThreadPool::shutdown`] should treat the jobs that are still in the queue.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ShutdownMode {
+    /// Execute every queued job, waiting for them all to finish. This is what [`ThreadPool::join_all`] does.
+    Drain,
+    /// Drop the queued jobs without executing them, waiting only for the jobs that are already running to finish.
+    /// Joining a handle to a dropped job returns [`Error::Dropped`].
+    Cancel,
+    /// Execute every queued job as with [`ShutdownMode::Drain`], but give up waiting for them once the given timeout
+    /// has elapsed.
+    DrainTimeout(Duration),
+}
+
+/// A hook that is invoked with the length of the queue when it crosses a threshold, given to
+/// [`ThreadPoolBuilder::on_queue_high_water`].
+type QueueHook = Arc<dyn Fn(usize) + Send + Sync>;
+
+/// A hook that is invoked with a job evicted from a full queue, given to [`ThreadPoolBuilder::on_job_evicted`].
+type EvictHook = Arc<dyn Fn(Job) + Send + Sync>;
+
+/// A hook that is invoked before a job is executed, given to [`ThreadPoolBuilder::on_job_start`].
+type JobStartHook = Arc<dyn Fn(JobInfo) + Send + Sync>;
+
+/// A hook that is invoked after a job is executed, given to [`ThreadPoolBuilder::on_job_end`].
+type JobEndHook = Arc<dyn Fn(JobInfo, Duration, JobOutcome) + Send + Sync>;
+
+/// A function used to spawn worker threads, given to [`ThreadPoolBuilder::with_spawner`].
+type Spawner = Arc<dyn Fn(ThreadConfig, Box<dyn FnOnce() + Send>) -> io::Result<JoinHandle<()>> + Send + Sync>;
+
+/// A handler that is invoked with the payload of a panic that occurred in a job, given to
+/// [`ThreadPoolBuilder::on_panic`].
+type PanicHandler = Arc<dyn Fn(&(dyn std::any::Any + Send)) + Send + Sync>;
+
+/// A function that wraps every job enqueued on a pool, given to [`ThreadPoolBuilder::wrap_jobs`].
+type JobWrapper = Arc<dyn for<'a> Fn(Box<dyn FnOnce() + Send + 'a>) -> Box<dyn FnOnce() + Send + 'a> + Send + Sync>;
+
+/// A function that creates the job queue of a pool, given to [`ThreadPoolBuilder::with_queue`].
+type QueueInit = Arc<dyn Fn() -> Box<dyn JobQueue> + Send + Sync>;
+
+/// A function that creates the state of a worker, given to [`ThreadPoolBuilder::with_worker_state`].
+type WorkerStateInit = Arc<dyn Fn(usize) -> Box<dyn std::any::Any> + Send + Sync>;
+
+/// A function that is invoked on each worker thread to warm it up, given to [`ThreadPoolBuilder::with_warmup`].
+type WarmupHook = Arc<dyn Fn() + Send + Sync>;
+
+/// A function that is invoked once when a pool shuts down, given to [`ThreadPoolBuilder::on_shutdown`].
+type ShutdownHook = Arc<Mutex<Option<Box<dyn FnOnce() + Send>>>>;
+
+/// A hook that is invoked on a worker thread, given the index of that worker.
+type WorkerHook = Arc<dyn Fn(usize) + Send + Sync>;
+
+/// A non-fatal problem that occurred while configuring a [`ThreadPool`] or one of its worker threads.
+///
+/// Warnings do not prevent the creation of a pool. By default, they are printed to stderr. This behaviour may be
+/// changed with [`ThreadPoolBuilder::on_warning`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Warning {
+    /// The environment variable given to [`ThreadPoolBuilder::with_env_override`] was set, but not to a positive
+    /// integer, so it was ignored.
+    InvalidEnvOverride {
+        /// The name of the environment variable.
+        var: String,
+        /// The value of the environment variable.
+        value: String,
+    },
+    /// A replacement for a worker that was due to be recycled could not be spawned, so the worker will continue to run.
+    Recycle {
+        /// The index of the worker.
+        worker: usize,
+        /// The error that occurred.
+        error: io::Error,
+    },
+    /// A worker could not be pinned to the requested core.
+    #[cfg(feature = "affinity")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "affinity")))]
+    CoreAffinity {
+        /// The index of the worker.
+        worker: usize,
+        /// The core that the worker should have been pinned to.
+        core: usize,
+    },
+    /// The CPU topology needed by [`ThreadPoolBuilder::with_affinity_strategy`] could not be read (for example,
+    /// because the system is not Linux), so workers were not pinned to cores.
+    #[cfg(feature = "affinity")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "affinity")))]
+    CpuTopology {
+        /// The error that occurred.
+        error: io::Error,
+    },
+    /// The scheduling priority of a worker could not be set.
+    #[cfg(feature = "priority")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "priority")))]
+    Priority {
+        /// The index of the worker.
+        worker: usize,
+        /// The error that occurred.
+        error: io::Error,
+    },
+    /// The realtime scheduling policy of a worker could not be set.
+    #[cfg(all(unix, feature = "realtime"))]
+    #[cfg_attr(docsrs, doc(cfg(all(unix, feature = "realtime"))))]
+    RealtimePolicy {
+        /// The index of the worker.
+        worker: usize,
+        /// The error that occurred.
+        error: io::Error,
+    },
+    /// The QoS class of a worker could not be set.
+    #[cfg(feature = "qos")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "qos")))]
+    QosClass {
+        /// The index of the worker.
+        worker: usize,
+        /// The error that occurred.
+        error: io::Error,
+    },
+    /// The IO priority of a worker could not be set.
+    #[cfg(feature = "ioprio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ioprio")))]
+    IoPriority {
+        /// The index of the worker.
+        worker: usize,
+        /// The error that occurred.
+        error: io::Error,
+    },
+    /// The NUMA nodes requested with [`ThreadPoolBuilder::with_numa_node`] or
+    /// [`ThreadPoolBuilder::with_numa_interleave`] could not be found (for example, because the system is not Linux),
+    /// so workers were not placed on NUMA nodes.
+    #[cfg(feature = "numa")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "numa")))]
+    NumaTopology {
+        /// The error that occurred.
+        error: io::Error,
+    },
+    /// A worker could not be placed on its NUMA node.
+    #[cfg(feature = "numa")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "numa")))]
+    NumaNode {
+        /// The index of the worker.
+        worker: usize,
+        /// The NUMA node that the worker should have been placed on.
+        node: usize,
+        /// The error that occurred.
+        error: io::Error,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::InvalidEnvOverride { ref var, ref value } => {
+                write!(f, "ignoring invalid thread count {:?} in environment variable {}", value, var)
+            },
+            Self::Recycle { worker, ref error } => write!(f, "failed to recycle worker {}: {}", worker, error),
+            #[cfg(feature = "affinity")]
+            Self::CoreAffinity { worker, core } => write!(f, "failed to pin worker {} to core {}", worker, core),
+            #[cfg(feature = "affinity")]
+            Self::CpuTopology { ref error } => write!(f, "failed to read CPU topology: {}", error),
+            #[cfg(feature = "priority")]
+            Self::Priority { worker, ref error } => write!(f, "failed to set priority of worker {}: {}", worker, error),
+            #[cfg(all(unix, feature = "realtime"))]
+            Self::RealtimePolicy { worker, ref error } => {
+                write!(f, "failed to set realtime policy of worker {}: {}", worker, error)
+            },
+            #[cfg(feature = "qos")]
+            Self::QosClass { worker, ref error } => {
+                write!(f, "failed to set QoS class of worker {}: {}", worker, error)
+            },
+            #[cfg(feature = "ioprio")]
+            Self::IoPriority { worker, ref error } => {
+                write!(f, "failed to set IO priority of worker {}: {}", worker, error)
+            },
+            #[cfg(feature = "numa")]
+            Self::NumaTopology { ref error } => {
+                write!(f, "failed to determine NUMA topology, so workers will not be placed on NUMA nodes: {}", error)
+            },
+            #[cfg(feature = "numa")]
+            Self::NumaNode { worker, node, ref error } => {
+                write!(f, "failed to place worker {} on NUMA node {}: {}", worker, node, error)
+            },
+        }
+    }
+}
+
 // TODO: Use when stable, see https://github.com/rust-lang/rust/issues/74465
 //static GLOBAL: std::lazy::SyncLazy<ThreadPool> = std::lazy::SyncLazy::new(|| ThreadPool::default());
 
 // I'll try spinning, that's a good trick! Actually, this isn't so bad: instead of spinning in a hot loop, we just
-// yield to the scheduler every time we fail to access the global pool. This prevents priority inversion.
-static GLOBAL: spin::once::Once<ThreadPool, spin::Yield> = spin::once::Once::new();
+// yield to the scheduler every time we fail to access the global pool. This prevents priority inversion. The pool is
+// leaked on initialization so that `'static` references to it may be handed out, and only reclaimed in
+// `ThreadPool::reset_global`.
+static GLOBAL: spin::rwlock::RwLock<Option<&'static ThreadPool>, spin::Yield> = spin::rwlock::RwLock::new(None);
 
 /// A pool of threads that may be used to execute jobs.
 pub struct ThreadPool {
-    tx: Sender<Job>,
-    handles: Vec<JoinHandle<()>>,
+    // Shared with pending continuations (see `JobHandle::then`), which may enqueue jobs after the pool is dropped
+    tx: Arc<queue::QueueSender>,
+    shared: Arc<Shared>,
 }
 
 impl Default for ThreadPool {
-    fn default() -> Self { Self::build().finish().unwrap() }
+    /// Create a [`ThreadPool`] with the default configuration, except that the thread count may be overridden with the
+    /// [`ThreadPool::THREADS_ENV_VAR`] environment variable.
+    fn default() -> Self { Self::build().with_env_override(Self::THREADS_ENV_VAR).finish().unwrap() }
+}
+
+impl fmt::Debug for ThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("ThreadPool");
+        s
+            .field("name", &self.name())
+            .field("thread_count", &self.thread_count())
+            .field("queue_len", &self.queue_len())
+            .field("idle_workers", &self.idle_workers())
+            .field("active_jobs", &self.active_jobs())
+            .field("closed", &self.is_closed())
+            .field("paused", &self.is_paused());
+        #[cfg(feature = "ioprio")]
+        s
+            .field("io_priority", &self.shared.config.io_priority)
+            .field("io_priority_applied", &self.shared.io_priority_applied.load(Ordering::Relaxed));
+        #[cfg(feature = "numa")]
+        s.field("worker_numa_nodes", &self.worker_numa_nodes());
+        #[cfg(feature = "affinity")]
+        s.field("worker_cores", &self.worker_cores());
+        s.finish()
+    }
 }
 
 impl ThreadPool {
@@ -116,12 +776,28 @@ impl ThreadPool {
     /// determined automatically.
     pub const DEFAULT_THREAD_COUNT: usize = 8;
 
+    /// The queue length, as a multiple of the thread count, beyond which [`ThreadPool::run_or_inline`] will execute
+    /// jobs on the calling thread.
+    pub const INLINE_THRESHOLD_FACTOR: usize = 4;
+
+    /// The environment variable that may be used to override the thread count of pools created with
+    /// [`ThreadPool::default`], including the global pool. See [`ThreadPoolBuilder::with_env_override`].
+    pub const THREADS_ENV_VAR: &'static str = "LAGOON_THREADS";
+
+    /// The name given to the global pool if it was not configured with [`ThreadPoolBuilder::with_pool_name`].
+    pub const GLOBAL_POOL_NAME: &'static str = "lagoon-global";
+
     /// Returns a reference to the global [`ThreadPool`], instantiating as with [`ThreadPool::default`] if it is not
     /// already initialized.
     ///
     /// This should be used when you don't require any specific thread pool configuration to avoid multiple thread
     /// pools fighting for scheduler time.
-    pub fn global() -> &'static Self { Self::global_with_builder(ThreadPoolBuilder::default()) }
+    ///
+    /// As with [`ThreadPool::default`], the thread count may be overridden with the [`ThreadPool::THREADS_ENV_VAR`]
+    /// environment variable.
+    pub fn global() -> &'static Self {
+        Self::global_with_builder(ThreadPoolBuilder::default().with_env_override(Self::THREADS_ENV_VAR))
+    }
 
     /// Returns a reference to the global [`ThreadPool`], initializing it with the given [`ThreadPoolBuilder`] if it
     /// is not already initialized.
@@ -134,12 +810,65 @@ impl ThreadPool {
     /// If your application has specific pool requirements (for example, most games require thread pools to use N - 1
     /// threads to ensure that at least one core is free at any given time to keep the main thread running smoothly
     /// without stuttering) you should use this function *as early as possible* in the program's execution (i.e: at the
-    /// top of the `main` function) to avoid dependencies initializing it first.
+    /// top of the `main` function) to avoid dependencies initializing it first. The N - 1 requirement may be expressed
+    /// with [`ThreadPoolBuilder::with_reserved_cores`].
     ///
     /// Note additionally that the configuration you choose might interfere with dependencies that also use the global
     /// thread pool. Choose sensible, accomodating defaults where possible.
+    ///
+    /// Unless the builder was given a name with [`ThreadPoolBuilder::with_pool_name`], the pool is named
+    /// [`ThreadPool::GLOBAL_POOL_NAME`].
     pub fn global_with_builder(builder: ThreadPoolBuilder) -> &'static Self {
-        GLOBAL.call_once(|| builder.finish().expect("Failed to initialise global thread pool"))
+        if let Some(pool) = *GLOBAL.read() {
+            return pool;
+        }
+        let mut global = GLOBAL.write();
+        // Another thread may have initialized the pool after we checked
+        match *global {
+            Some(pool) => pool,
+            None => {
+                let builder = match builder.pool_name {
+                    Some(_) => builder,
+                    None => builder.with_pool_name(Self::GLOBAL_POOL_NAME.to_string()),
+                };
+                let pool = Box::leak(Box::new(builder.finish().expect("Failed to initialise global thread pool")));
+                *global = Some(pool);
+                pool
+            },
+        }
+    }
+
+    /// Remove the global [`ThreadPool`], returning it if it was initialized. The next call to [`ThreadPool::global`]
+    /// or [`ThreadPool::global_with_builder`] will initialize a new global pool.
+    ///
+    /// This is intended for tests that need a fresh global pool configuration, or that need to verify that the global
+    /// pool is torn down correctly (for example, by calling [`ThreadPool::join_all`] on the returned pool).
+    ///
+    /// # Safety
+    ///
+    /// The global pool is freed when the returned pool is dropped, so references to it previously returned by
+    /// [`ThreadPool::global`] or [`ThreadPool::global_with_builder`] must not be used afterwards. This includes
+    /// references that have been captured by jobs, and those held by other threads (including threads spawned by
+    /// dependencies). Tests that call this function should not run in parallel with other tests that use the global
+    /// pool.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::global_with_builder(lagoon::ThreadPool::build().with_thread_count(2));
+    /// assert_eq!(pool.thread_count(), 2);
+    ///
+    /// // Safety: `pool` is not used after this point
+    /// let old = unsafe { lagoon::ThreadPool::reset_global() }.unwrap();
+    /// old.join_all().unwrap();
+    ///
+    /// let pool = lagoon::ThreadPool::global_with_builder(lagoon::ThreadPool::build().with_thread_count(3));
+    /// assert_eq!(pool.thread_count(), 3);
+    /// ```
+    pub unsafe fn reset_global() -> Option<Self> {
+        GLOBAL
+            .write()
+            .take()
+            // Safety: the pool was leaked from a box on initialization, and the caller guarantees that it is unused
+            .map(|pool| *Box::from_raw(pool as *const Self as *mut Self))
     }
 
     /// Begin building a new [`ThreadPool`].
@@ -148,11 +877,234 @@ impl ThreadPool {
     }
 
     /// Returns the number of threads in this pool.
-    pub fn thread_count(&self) -> usize { self.handles.len() }
+    ///
+    /// If the pool doesn't spawn its threads eagerly (see [`ThreadPoolBuilder::with_spawn_mode`]), this is the number
+    /// of threads that the pool will have once they are spawned. See [`ThreadPool::spawned_threads`].
+    pub fn thread_count(&self) -> usize { self.shared.thread_count }
+
+    /// Returns the name of this pool, given to [`ThreadPoolBuilder::with_pool_name`].
+    pub fn name(&self) -> Option<&str> { self.shared.config.pool_name.as_deref() }
+
+    /// Returns the configuration of this pool.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(3)
+    ///     .with_thread_name("worker".to_string())
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(pool.config().thread_count, 3);
+    /// assert_eq!(pool.config().thread_name.as_deref(), Some("worker"));
+    /// ```
+    pub fn config(&self) -> &PoolConfig { &self.shared.resolved }
+
+    /// Returns a [`ThreadPoolBuilder`] with the same configuration as this pool, including its hooks. Finishing the
+    /// builder creates a pool just like this one, and the builder may be modified beforehand to create a variation.
+    ///
+    /// The builder uses the thread count that this pool ended up with rather than the options that it was derived from
+    /// (such as [`ThreadPoolBuilder::with_reserved_cores`] or [`ThreadPoolBuilder::with_env_override`]), so the new
+    /// pool has the same number of threads even if the environment has since changed.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_name("worker".to_string())
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// // A temporary pool with the same settings, but only 2 threads
+    /// let burst = pool.to_builder().with_thread_count(2).finish().unwrap();
+    /// assert_eq!(burst.thread_count(), 2);
+    /// assert_eq!(burst.config().thread_name.as_deref(), Some("worker"));
+    ///
+    /// let copy = pool.to_builder().finish().unwrap();
+    /// assert_eq!(copy.thread_count(), pool.thread_count());
+    /// ```
+    pub fn to_builder(&self) -> ThreadPoolBuilder {
+        ThreadPoolBuilder {
+            thread_count: Some(self.thread_count()),
+            reserved_cores: None,
+            thread_count_fraction: None,
+            physical_cores_only: false,
+            env_override: None,
+            preset: None,
+            ..self.shared.config.clone()
+        }
+    }
+
+    /// Returns the number of threads in this pool that have actually been spawned.
+    ///
+    /// This is always equal to [`ThreadPool::thread_count`] unless the pool doesn't spawn its threads eagerly (see
+    /// [`ThreadPoolBuilder::with_spawn_mode`]).
+    pub fn spawned_threads(&self) -> usize { self.shared.spawned.load(Ordering::Acquire) }
+
+    /// Returns the number of jobs executed by this pool that have panicked, regardless of the [`PanicPolicy`].
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// for i in 0..10 {
+    ///     pool.run(move || if i % 2 == 0 { panic!("oh no") });
+    /// }
+    ///
+    /// while pool.panic_count() < 5 { std::thread::yield_now(); }
+    /// ```
+    pub fn panic_count(&self) -> usize { self.shared.panicked.load(Ordering::Relaxed) }
+
+    /// Returns the number of workers in this pool that are currently idle, waiting for a job.
+    ///
+    /// This is a snapshot that may be out of date as soon as it is returned, but it is useful for adaptive schedulers
+    /// that need to decide whether to enqueue more work or to do it inline. It never exceeds
+    /// [`ThreadPool::spawned_threads`], although a worker that has just finished a job (or that has just been spawned)
+    /// will briefly not be counted.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(2).finish().unwrap();
+    ///
+    /// let barrier = std::sync::Arc::new(std::sync::Barrier::new(3));
+    /// for _ in 0..2 {
+    ///     let barrier = barrier.clone();
+    ///     pool.run(move || { barrier.wait(); });
+    /// }
+    /// while pool.queue_len() > 0 || pool.idle_workers() > 0 { std::thread::yield_now(); }
+    ///
+    /// // Release the workers
+    /// barrier.wait();
+    /// while pool.idle_workers() < 2 { std::thread::yield_now(); }
+    /// ```
+    pub fn idle_workers(&self) -> usize { self.shared.idle.load(Ordering::Acquire) }
+
+    /// Ensure that all of the threads in this pool have been spawned, spawning them if the pool doesn't spawn its
+    /// threads eagerly (see [`ThreadPoolBuilder::with_spawn_mode`]) and they have not yet been.
+    ///
+    /// If a thread could not be spawned, an error is returned and spawning will be reattempted the next time this
+    /// function is called or a job is enqueued.
+    pub fn ready(&self) -> Result<(), Error> {
+        if self.spawned_threads() < self.thread_count() {
+            worker::spawn_workers(&self.shared, self.thread_count())
+        } else {
+            Ok(())
+        }
+    }
+
+    // Spawn the threads that are needed to execute a job that is about to be enqueued
+    fn spawn_as_needed(&self) -> Result<(), Error> {
+        match self.shared.config.spawn_mode {
+            SpawnMode::Eager | SpawnMode::OnFirstJob => self.ready(),
+            SpawnMode::OnDemand => {
+                // Only spawn another thread if none of the existing ones are free to take the job
+                let spawned = self.spawned_threads();
+                if spawned < self.thread_count() && self.idle_workers() == 0 {
+                    worker::spawn_workers(&self.shared, spawned + 1)
+                } else {
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    /// Returns the number of worker threads that have been replaced by fresh threads due to the policy given to
+    /// [`ThreadPoolBuilder::with_thread_recycling`].
+    pub fn threads_recycled(&self) -> usize { self.shared.threads_recycled.load(Ordering::Relaxed) }
+
+    /// Returns the number of jobs that each worker has executed, indexed by worker. This is useful for checking whether
+    /// work is evenly distributed across the pool.
+    ///
+    /// Only jobs that workers take from the queue are counted. Jobs executed elsewhere, such as by a thread waiting on
+    /// a scope or by [`FullQueuePolicy::RunInline`], are not. A recycled worker's replacement carries on its count.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(4).finish().unwrap();
+    ///
+    /// for _ in 0..100 {
+    ///     pool.run(|| {});
+    /// }
+    /// pool.wait_idle();
+    ///
+    /// let counts = pool.per_worker_counts();
+    /// assert_eq!(counts.len(), 4);
+    /// assert_eq!(counts.iter().sum::<usize>(), 100);
+    /// ```
+    #[cfg(feature = "metrics")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+    pub fn per_worker_counts(&self) -> Vec<usize> {
+        self.shared.jobs_executed.iter().map(|count| count.load(Ordering::Relaxed)).collect()
+    }
+
+    /// Returns, for each spawned worker, whether the realtime scheduling policy given to
+    /// [`ThreadPoolBuilder::with_realtime_policy`] was applied successfully. The list is empty if no policy was set.
+    #[cfg(all(unix, feature = "realtime"))]
+    #[cfg_attr(docsrs, doc(cfg(all(unix, feature = "realtime"))))]
+    pub fn realtime_policy_results(&self) -> Vec<io::Result<()>> {
+        self.shared.realtime_results
+            .lock()
+            .unwrap()
+            .iter()
+            .flatten()
+            .map(|res| res.as_ref().map(|_| ()).map_err(os::copy_error))
+            .collect()
+    }
+
+    /// Returns, for each worker, the NUMA node that it was placed on by [`ThreadPoolBuilder::with_numa_node`] or
+    /// [`ThreadPoolBuilder::with_numa_interleave`], or `None` if it was not placed on a node (because placement was
+    /// not requested or failed, or because the worker has not yet started).
+    #[cfg(feature = "numa")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "numa")))]
+    pub fn worker_numa_nodes(&self) -> Vec<Option<usize>> { self.shared.numa_placed.lock().unwrap().clone() }
+
+    /// Returns, for each worker, the core that it is pinned to by [`ThreadPoolBuilder::with_core_affinity`] or
+    /// [`ThreadPoolBuilder::with_affinity_strategy`], or `None` if it is not pinned.
+    ///
+    /// This is the assignment that the pool computed: if a worker failed to pin itself to its core, a
+    /// [`Warning::CoreAffinity`] was emitted instead.
+    #[cfg(feature = "affinity")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "affinity")))]
+    pub fn worker_cores(&self) -> Vec<Option<usize>> {
+        let cores = &self.shared.core_affinity;
+        (0..self.thread_count()).map(|idx| cores.get(idx % cores.len().max(1)).copied()).collect()
+    }
 
     /// Returns the number of jobs waiting to be executed.
     pub fn queue_len(&self) -> usize { self.tx.len() }
 
+    /// Returns the number of jobs that are currently being executed.
+    pub fn active_jobs(&self) -> usize { self.shared.active.load(Ordering::Acquire) }
+
+    /// Block the current thread until every job that has been enqueued on this pool has finished, including those that
+    /// panicked, without shutting the pool down.
+    ///
+    /// Jobs enqueued while waiting (including by other jobs) will also be waited upon. This is useful for workloads
+    /// that proceed in phases, where every job in one phase must finish before the next phase is enqueued.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if it is called from within a job running on this pool, since that job can't finish
+    /// until the function returns.
+    ///
+    /// ```
+    /// use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    ///
+    /// let pool = lagoon::ThreadPool::default();
+    /// let counter = Arc::new(AtomicUsize::new(0));
+    ///
+    /// for phase in 1..=3 {
+    ///     for _ in 0..10 {
+    ///         let counter = counter.clone();
+    ///         pool.run(move || { counter.fetch_add(1, Ordering::Relaxed); });
+    ///     }
+    ///     pool.wait_idle();
+    ///     assert_eq!(counter.load(Ordering::Relaxed), phase * 10);
+    ///     assert_eq!(pool.queue_len() + pool.active_jobs(), 0);
+    /// }
+    /// ```
+    pub fn wait_idle(&self) {
+        assert!(!self.is_current_worker(), "wait_idle called from within a job running on the same pool");
+        let lock = self.shared.quiescent_lock.lock().unwrap();
+        let _lock = self.shared.quiescent
+            .wait_while(lock, |_| self.shared.outstanding.load(Ordering::Acquire) > 0)
+            .unwrap();
+    }
+
     /// Enqueue a function to be executed as a job when a thread is free to do so.
     ///
     /// ```
@@ -162,105 +1114,2516 @@ impl ThreadPool {
     ///     pool.run(move || println!("I am the {}th job!", i));
     /// }
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the pool has been closed with [`ThreadPool::close`], if the pool spawns its threads
+    /// lazily (see [`ThreadPoolBuilder::with_spawn_mode`]) and a thread could not be spawned, or if the queue is full
+    /// and the pool uses [`FullQueuePolicy::Fail`]. Use [`ThreadPool::try_run`] if this is a possibility.
     pub fn run<F: FnOnce() + Send + 'static>(&self, f: F) {
-        self.tx.send(Job { f: Box::new(f) }).unwrap()
+        self.try_run(f).expect("Failed to enqueue job")
     }
 
-    /// Enqueue a function to be executed as a job when a thread is free to do so, returning a handle that allows
-    /// retrieval of the return value of the function.
-    #[cfg(feature = "recv")]
-    pub fn run_recv<F: FnOnce() -> R + Send + 'static, R: Send + 'static>(&self, f: F) -> recv::JobHandle<R> {
-        let (tx, rx) = oneshot::channel();
-        self.run(move || { let _ = tx.send(f()); });
-        recv::JobHandle::new(rx)
+    /// Enqueue a function to be executed as a job when a thread is free to do so, returning [`Error::Closed`] if the
+    /// pool has been closed with [`ThreadPool::close`].
+    ///
+    /// If the pool spawns its threads lazily (see [`ThreadPoolBuilder::with_spawn_mode`]) and this job needs more of
+    /// them, they are spawned first and any error that occurs while doing so is returned. If the queue is full, the
+    /// pool's [`FullQueuePolicy`] is applied.
+    pub fn try_run<F: FnOnce() + Send + 'static>(&self, f: F) -> Result<(), Error> {
+        self.try_run_job(None, Box::new(f))
     }
 
-    /// Signal to threads (not jobs) that they should stop, then wait for them to finish processing jobs.
+    /// Enqueue an already-boxed function to be executed as a job when a thread is free to do so.
     ///
-    /// All outstanding jobs will be executed before this function returns.
-    pub fn join_all(self) -> thread::Result<()> {
-        let Self { tx, handles } = self;
-        drop(tx);
-        for handle in handles {
-            handle.join()?;
-        }
-        Ok(())
+    /// Jobs are stored as boxed functions, so this is equivalent to [`ThreadPool::run`] but avoids boxing the function
+    /// a second time. This is useful when jobs have been type-erased before they reach the pool.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let jobs: Vec<Box<dyn FnOnce() + Send>> = vec![Box::new(|| println!("Hello")), Box::new(|| println!("world!"))];
+    /// for job in jobs {
+    ///     pool.run_boxed(job);
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This function panics in the same cases as [`ThreadPool::run`].
+    pub fn run_boxed(&self, f: Box<dyn FnOnce() + Send + 'static>) {
+        self.try_run_job(None, f).expect("Failed to enqueue job")
     }
 
-    /// Create a scope that allows the spawning of threads with safe access to the current scope.
+    /// Enqueue a function to be executed as a job when a thread is free to do so, giving it a name that identifies it
+    /// in diagnostics.
     ///
-    /// This function will wait for all jobs created in the scope to finish before continuing. See [`Scope`] for more
-    /// information about scoped jobs.
-    #[cfg(feature = "scope")]
-    pub fn scoped<'pool, 'scope, F: FnOnce(scope::Scope<'pool, 'scope>) -> R, R>(&'pool self, f: F) -> R {
-        scope::run(self, f)
+    /// The name is passed to the hooks given to [`ThreadPoolBuilder::on_job_start`] and
+    /// [`ThreadPoolBuilder::on_job_end`] as part of the [`JobInfo`], and is available from [`current_job`] while the
+    /// job is running (including from within the handler given to [`ThreadPoolBuilder::on_panic`]).
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let names = Arc::new(Mutex::new(Vec::new()));
+    /// let names2 = names.clone();
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .on_job_start(move |info| names2.lock().unwrap().push(info.name))
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// pool.run_named("resize-thumbnail", || {});
+    /// pool.join_all().unwrap();
+    /// assert_eq!(names.lock().unwrap()[0].as_deref(), Some("resize-thumbnail"));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This function panics in the same cases as [`ThreadPool::run`].
+    pub fn run_named<F: FnOnce() + Send + 'static>(&self, name: impl Into<String>, f: F) {
+        self.try_run_job(Some(name.into().into()), Box::new(f)).expect("Failed to enqueue job")
     }
-}
 
-/// A type used to configure a [`ThreadPool`] prior to its creation.
-#[derive(Clone, Default)]
-pub struct ThreadPoolBuilder {
-    thread_count: Option<usize>,
-    thread_name: Option<String>,
-    thread_stack_size: Option<usize>,
-}
+    /// Enqueue a single job that calls a function `count` times in sequence, passing it the index of each call.
+    ///
+    /// Unlike enqueueing `count` separate jobs, the function is only boxed and scheduled once, and may keep state
+    /// between calls. All of the calls happen on the same worker, so this is best suited to many cheap calls whose
+    /// scheduling overhead would otherwise dominate. If a call panics, the remaining calls are skipped.
+    ///
+    /// ```
+    /// use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    ///
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let sum = Arc::new(AtomicUsize::new(0));
+    /// let sum2 = sum.clone();
+    /// let mut calls = 0;
+    /// pool.run_repeat(100, move |i| {
+    ///     calls += 1;
+    ///     sum2.fetch_add(i * calls, Ordering::Relaxed);
+    /// });
+    ///
+    /// pool.join_all().unwrap();
+    /// assert_eq!(sum.load(Ordering::Relaxed), (0..100).map(|i| i * (i + 1)).sum::<usize>());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This function panics in the same cases as [`ThreadPool::run`].
+    pub fn run_repeat<F: FnMut(usize) + Send + 'static>(&self, count: usize, mut f: F) {
+        self.run(move || (0..count).for_each(&mut f))
+    }
 
-impl ThreadPoolBuilder {
-    /// Configure the [`ThreadPool`] with the given number of threads. If unspecified, the thread pool will attempt to
-    /// detect the number of hardware threads available to the process and use that. If this also fails,
-    /// [`ThreadPool::DEFAULT_THREAD_COUNT`] will be used.
-    pub fn with_thread_count(self, thread_count: usize) -> Self {
-        Self { thread_count: Some(thread_count), ..self }
+    fn try_run_job(&self, name: Option<Arc<str>>, f: Box<dyn FnOnce() + Send>) -> Result<(), Error> {
+        if self.is_closed() {
+            Err(Error::Closed)
+        } else {
+            self.spawn_as_needed()?;
+            self.shared.outstanding.fetch_add(1, Ordering::AcqRel);
+            if let Err(err) = self.send(self.job(name, f)) {
+                self.shared.finish_job();
+                return Err(err);
+            }
+            #[cfg(feature = "scope")]
+            self.shared.temporaries.notify_job();
+            self.check_high_water();
+            Ok(())
+        }
     }
 
-    /// Give the threads owned by this [`ThreadPool`] the given name. If unspecified, the default name will be the same
-    /// as those created by [`std::thread::spawn`].
-    pub fn with_thread_name(self, name: String) -> Self {
-        Self { thread_name: Some(name), ..self }
+    // Enqueue a job, applying the full queue policy
+    fn send(&self, mut job: Job) -> Result<(), Error> {
+        use channel::TrySendError;
+        if self.shared.config.inline_overflow.is_some_and(|threshold| self.queue_len() > threshold) {
+            worker::execute(&self.shared, job);
+            return Ok(());
+        }
+        let policy = self.shared.config.full_queue_policy;
+        if policy == FullQueuePolicy::Block {
+            return self.tx.send(job).map_err(|_| Error::Closed);
+        }
+        loop {
+            job = match self.tx.try_send(job) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(_)) => return Err(Error::Closed),
+                Err(TrySendError::Full(job)) => job,
+            };
+            match policy {
+                FullQueuePolicy::Block => unreachable!(),
+                FullQueuePolicy::Fail => return Err(Error::QueueFull),
+                FullQueuePolicy::RunInline => {
+                    worker::execute(&self.shared, job);
+                    return Ok(());
+                },
+                // The queue may have been drained in the meantime, in which case there's nothing to evict
+                FullQueuePolicy::DropOldest => if let Some(oldest) = self.shared.rx.pop_oldest() {
+                    match &self.shared.config.on_job_evicted {
+                        Some(hook) => {
+                            let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| hook(oldest)));
+                        },
+                        None => drop(oldest),
+                    }
+                    self.shared.finish_job();
+                },
+            }
+        }
     }
 
-    /// Give the threads owned by this [`ThreadPool`] a specific stack size. If unspecified, the default stack size
-    /// will be the same as those created by [`std::thread::spawn`].
-    pub fn with_thread_stack_size(self, size: usize) -> Self {
-        Self { thread_stack_size: Some(size), ..self }
+    // Invoke the high water hook if the queue has just grown past its threshold
+    fn check_high_water(&self) {
+        if let Some((threshold, hook)) = &self.shared.config.on_queue_high_water {
+            let len = self.queue_len();
+            let above = len >= *threshold;
+            let was_above = self.shared.above_high_water.swap(above, Ordering::Relaxed);
+            if above && !was_above {
+                let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| hook(len)));
+            }
+        }
     }
 
-    /// Finish configuration, returning a [`ThreadPool`].
-    pub fn finish(self) -> Result<ThreadPool, Error> {
-        let thread_count = self.thread_count
-            .or_else(|| available_concurrency())
-            .unwrap_or(ThreadPool::DEFAULT_THREAD_COUNT);
-
-        if thread_count == 0 {
-            return Err(Error::NoThreads);
-        }
-
-        let (tx, rx) = unbounded();
-
-        Ok(ThreadPool {
-            tx,
-            handles: (0..thread_count)
-                .map(|_| {
-                    let rx = rx.clone();
-                    let builder = thread::Builder::new();
-                    let builder = match self.thread_name.clone() {
-                        Some(name) => builder.name(name),
-                        None => builder,
-                    };
-                    let builder = match self.thread_stack_size {
-                        Some(size) => builder.stack_size(size),
-                        None => builder,
-                    };
-                    builder.spawn(move || {
-                        while let Ok(job) = rx.recv() {
-                            let job = std::panic::AssertUnwindSafe(job);
-                            let _ = std::panic::catch_unwind(move || {
-                                (job.0.f)();
-                            });
-                        }
-                    }).map_err(Error::Io)
-                })
-                .collect::<Result<_, _>>()?,
+    /// Enqueue a function to be executed as a job when a thread is free to do so, returning how long the caller was
+    /// blocked waiting for space in the queue.
+    ///
+    /// This is useful for diagnosing backpressure in pools with a queue capacity (see
+    /// [`ThreadPoolBuilder::with_queue_capacity`]). When space is immediately available, as is always the case for
+    /// pools with an unbounded queue, the duration returned is near-zero.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(1)
+    ///     .with_queue_capacity(1)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let blocked = (0..4)
+    ///     .map(|_| pool.run_blocking_timed(|| std::thread::sleep(Duration::from_millis(20))))
+    ///     .sum::<Duration>();
+    /// assert!(blocked >= Duration::from_millis(20));
+    /// ```
+    pub fn run_blocking_timed<F: FnOnce() + Send + 'static>(&self, f: F) -> Duration {
+        // Spawn any lazily-spawned workers first, so that only time spent waiting for the queue is measured
+        self.spawn_as_needed().expect("Failed to spawn worker threads");
+        let start = Instant::now();
+        self.run(f);
+        start.elapsed()
+    }
+
+    /// Enqueue a function to be executed as a job when a thread is free to do so or, if the pool is heavily loaded,
+    /// execute it immediately on the calling thread.
+    ///
+    /// The pool is considered heavily loaded when more than `thread_count() * INLINE_THRESHOLD_FACTOR` jobs are waiting
+    /// (see [`ThreadPool::INLINE_THRESHOLD_FACTOR`]). This is a crude but effective form of backpressure that bounds
+    /// the latency of the job, at the cost of blocking the caller while it runs. A panic that occurs during inline
+    /// execution is handled according to the pool's [`PanicPolicy`], just as with jobs executed by the pool.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// for i in 0..1000 {
+    ///     pool.run_or_inline(move || println!("I am the {}th job!", i));
+    /// }
+    /// ```
+    pub fn run_or_inline<F: FnOnce() + Send + 'static>(&self, f: F) {
+        if !self.shared.config.deterministic && self.queue_len() > self.thread_count() * Self::INLINE_THRESHOLD_FACTOR {
+            self.shared.outstanding.fetch_add(1, Ordering::AcqRel);
+            worker::execute(&self.shared, self.job(None, Box::new(f)));
+        } else {
+            self.run(f);
+        }
+    }
+
+    /// Apply a function to every item in a list in parallel, blocking until all are finished and returning the results
+    /// in the same order as the items.
+    ///
+    /// Each result is written directly into its own slot of the output, so no sorting is required. See
+    /// [`ThreadPool::scoped_collect`] (with the `scope` feature) for a variant that allows borrowing from the caller.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let squares = pool.map_collect((0..100).collect(), |x: u32| x * x);
+    /// assert!((0..100).map(|x| x * x).eq(squares.into_iter()));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the function panicked for any item, since its result is unavailable.
+    pub fn map_collect<T, F, R>(&self, items: Vec<T>, f: F) -> Vec<R>
+    where
+        T: Send + 'static,
+        F: Fn(T) -> R + Send + Sync + 'static,
+        R: Send + 'static,
+    {
+        let f = Arc::new(f);
+        let slots = Arc::new((0..items.len()).map(|_| Mutex::new(None)).collect::<Vec<_>>());
+
+        let group = self.group();
+        for (idx, item) in items.into_iter().enumerate() {
+            let f = f.clone();
+            let slots = slots.clone();
+            group.run(move || *slots[idx].lock().unwrap() = Some(f(item)));
+        }
+        group.wait();
+
+        slots
+            .iter()
+            .map(|slot| slot.lock().unwrap().take().expect("a job panicked"))
+            .collect()
+    }
+
+    /// Stop the pool from accepting new jobs. Jobs that have already been enqueued will still be executed.
+    ///
+    /// Unlike [`ThreadPool::join_all`], this function does not consume the pool, so it may be called through a shared
+    /// reference (for example, to begin a graceful shutdown of a server from a signal handler thread). After closing,
+    /// [`ThreadPool::try_run`] will return [`Error::Closed`] and [`ThreadPool::run`] will panic.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// pool.run(|| println!("Hello!"));
+    /// pool.close();
+    /// assert!(pool.try_run(|| println!("Goodbye!")).is_err());
+    ///
+    /// // Scoped jobs can't be enqueued either, so the scope panics instead of waiting for them
+    /// # #[cfg(feature = "scope")]
+    /// assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pool.scoped(|s| s.run(|| {})))).is_err());
+    ///
+    /// // Outstanding jobs are still executed
+    /// pool.join_all().unwrap();
+    /// ```
+    pub fn close(&self) { self.shared.closed.store(true, Ordering::Release); }
+
+    /// Returns whether the pool has been closed with [`ThreadPool::close`].
+    pub fn is_closed(&self) -> bool { self.shared.closed.load(Ordering::Acquire) }
+
+    /// Pause the pool. Workers will not begin executing new jobs until the pool is resumed with
+    /// [`ThreadPool::resume`], although jobs may still be enqueued.
+    ///
+    /// Jobs that are already running are unaffected, unless they cooperatively check for pausing with
+    /// [`JobContext::yield_now`].
+    pub fn pause(&self) { self.shared.pause.set_paused(true); }
+
+    /// Resume a pool that was paused with [`ThreadPool::pause`].
+    pub fn resume(&self) { self.shared.pause.set_paused(false); }
+
+    /// Returns whether the pool has been paused with [`ThreadPool::pause`].
+    pub fn is_paused(&self) -> bool { self.shared.pause.is_paused() }
+
+    /// Enqueue a function to be executed as a job when a thread is free to do so, passing it a [`JobContext`] that
+    /// allows it to cooperatively respond to pausing and cancellation.
+    ///
+    /// Returns a [`CancelToken`] that may be used to request cancellation of the job. If the job is cancelled before it
+    /// starts, it will not be executed at all.
+    pub fn run_ctx<F: FnOnce(&JobContext) + Send + 'static>(&self, f: F) -> CancelToken {
+        let token = CancelToken::default();
+        let job_token = token.clone();
+        let pause = self.shared.pause.clone();
+        let pool_id = self.id();
+        self.run(move || {
+            if !job_token.is_cancelled() {
+                f(&JobContext::new(&pause, &job_token, pool_id));
+            }
+        });
+        token
+    }
+
+    /// Enqueue a function to be executed as a job when a thread is free to do so, passing it a [`JobContext`] that
+    /// allows it to cooperatively respond to pausing and cancellation. Returns a handle that allows retrieval of the
+    /// return value of the function.
+    ///
+    /// The job's cancellation token is shared with its handle, so it's cancelled by [`JobHandle::cancel`] and
+    /// [`AbortHandle::abort`].
+    #[cfg(feature = "recv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "recv")))]
+    pub fn run_recv_ctx<F, R>(&self, f: F) -> recv::JobHandle<R>
+    where
+        F: FnOnce(&JobContext) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, handle) = recv::JobHandle::new();
+        let (shared, token, pool_id) = (self.shared.clone(), tx.token().clone(), self.id());
+        self.run(move || tx.run(|| {
+            let ctx = JobContext::new(&shared.pause, &token, pool_id);
+            unwind::catch(&shared, || f(&ctx)).map_err(Error::Panicked)
+        }));
+        handle
+    }
+
+    /// Enqueue a function to be executed as a job when a thread is free to do so, returning a handle that allows
+    /// retrieval of the return value of the function.
+    #[cfg(feature = "recv")]
+    pub fn run_recv<F: FnOnce() -> R + Send + 'static, R: Send + 'static>(&self, f: F) -> recv::JobHandle<R> {
+        self.run_recv_with(move |shared| unwind::catch(shared, f).map_err(Error::Panicked))
+    }
+
+    /// Enqueue a function to be executed as a job when a thread is free to do so, unless the deadline passes before a
+    /// thread becomes free. Returns a handle that allows retrieval of the return value of the function.
+    ///
+    /// The deadline is checked immediately before the job starts. If it has passed, the function is skipped and
+    /// joining the handle returns [`Error::Skipped`]. This avoids wasting time on work that has become stale while the
+    /// pool is overloaded.
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(1).finish().unwrap();
+    ///
+    /// // Occupy the only worker so that the job can't start before its deadline
+    /// pool.run(|| std::thread::sleep(Duration::from_millis(50)));
+    /// let job = pool.run_before(Instant::now() + Duration::from_millis(10), || 42);
+    /// assert!(matches!(job.join(), Err(lagoon::Error::Skipped)));
+    /// ```
+    #[cfg(feature = "recv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "recv")))]
+    pub fn run_before<F, R>(&self, deadline: Instant, f: F) -> recv::JobHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run_recv_with(move |shared| if Instant::now() < deadline {
+            unwind::catch(shared, f).map_err(Error::Panicked)
+        } else {
+            Err(Error::Skipped)
+        })
+    }
+
+    // Enqueue a function that produces the result of a job with a handle
+    #[cfg(feature = "recv")]
+    fn run_recv_with<F, R>(&self, f: F) -> recv::JobHandle<R>
+    where
+        F: FnOnce(&Shared) -> Result<R, Error> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, handle) = recv::JobHandle::new();
+        let shared = self.shared.clone();
+        self.run(move || tx.run(|| f(&shared)));
+        handle
+    }
+
+    /// Enqueue a function with a small [`Copy`] result to be executed as a job when a thread is free to do so,
+    /// returning a handle that allows retrieval of the return value of the function.
+    ///
+    /// This is equivalent to [`ThreadPool::run_recv`], except that the result is stored inline in an atomic cell
+    /// rather than being sent through a channel, avoiding an allocation. This is an optimisation for jobs that return
+    /// small values like integers.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let jobs = (0..10u32)
+    ///     .map(|i| pool.run_recv_small(move || i * i))
+    ///     .collect::<Vec<_>>();
+    ///
+    /// for (i, job) in jobs.into_iter().enumerate() {
+    ///     assert_eq!(job.join().unwrap(), (i * i) as u32);
+    /// }
+    /// ```
+    #[cfg(feature = "recv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "recv")))]
+    pub fn run_recv_small<F, R>(&self, f: F) -> recv::SmallJobHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Copy + Send + 'static,
+    {
+        let (tx, handle) = recv::SmallJobHandle::new();
+        let shared = self.shared.clone();
+        self.run(move || tx.send(unwind::catch(&shared, f)));
+        handle
+    }
+
+    /// Enqueue a function to be executed as a job when a thread is free to do so, returning a handle that may be cloned
+    /// to allow several consumers to retrieve the return value of the function. See [`SharedJobHandle`] for more
+    /// information.
+    #[cfg(feature = "recv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "recv")))]
+    pub fn run_recv_shared<F, R>(&self, f: F) -> recv::SharedJobHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Clone + Send + 'static,
+    {
+        let (tx, handle) = recv::SharedJobHandle::new();
+        let shared = self.shared.clone();
+        self.run(move || tx.send(unwind::catch(&shared, f).map_err(Error::Panicked)));
+        handle
+    }
+
+    /// Enqueue a function to be executed as a job when a thread is free to do so, sending its return value to the
+    /// given slot. The result may be retrieved with [`ResultSlot::join`], after which the slot may be reused.
+    ///
+    /// This is equivalent to [`ThreadPool::run_recv`], except that the result is stored in an existing slot rather
+    /// than being sent through a newly allocated channel. See [`ResultSlot`] for more information.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the slot is not empty (i.e: it's in use by another job, or its result has not yet
+    /// been retrieved).
+    #[cfg(feature = "recv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "recv")))]
+    pub fn run_recv_into<F, R>(&self, slot: &recv::ResultSlot<R>, f: F)
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let tx = slot.sender();
+        let shared = self.shared.clone();
+        self.run(move || tx.send(unwind::catch(&shared, f).map_err(Error::Panicked)));
+    }
+
+    /// Enqueue a function to be executed as a job when a thread is free to do so, returning a handle that allows
+    /// retrieval of the type-erased return value of the function.
+    ///
+    /// This is a thin wrapper around [`ThreadPool::run_recv`]. See [`BoxedJobHandle`] for more information.
+    #[cfg(feature = "recv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "recv")))]
+    pub fn run_recv_boxed<F, R>(&self, f: F) -> recv::BoxedJobHandle
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: std::any::Any + Send,
+    {
+        recv::BoxedJobHandle::new(self.run_recv(move || Box::new(f()) as Box<dyn std::any::Any + Send>))
+    }
+
+    /// Enqueue a function to be executed as a job when a thread is free to do so, giving it mutable access to the
+    /// state of the worker that executes it. See [`ThreadPoolBuilder::with_worker_state`].
+    ///
+    /// The job panics if the pool was not configured with worker state of type `S`. Since the job is given exclusive
+    /// access to the state, it also panics if it is executed while another job on the same worker is using the state
+    /// (which only happens if a job waits on a scope, and the worker executes this job in the meantime).
+    pub fn run_with_state<S: 'static, F: FnOnce(&mut S) + Send + 'static>(&self, f: F) {
+        self.run(move || worker::with_state(f));
+    }
+
+    /// Enqueue a function to be executed as a job when a thread is free to do so, giving it mutable access to the
+    /// state of the worker that executes it and returning a handle that allows retrieval of the return value of the
+    /// function. See [`ThreadPool::run_with_state`].
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_worker_state(|_| Vec::<u32>::new())
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let job = pool.run_recv_with_state(|buf: &mut Vec<u32>| {
+    ///     buf.clear();
+    ///     buf.extend(0..10);
+    ///     buf.iter().sum::<u32>()
+    /// });
+    /// assert_eq!(job.join().unwrap(), 45);
+    /// ```
+    #[cfg(feature = "recv")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "recv")))]
+    pub fn run_recv_with_state<S, F, R>(&self, f: F) -> recv::JobHandle<R>
+    where
+        S: 'static,
+        F: FnOnce(&mut S) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run_recv(move || worker::with_state(f))
+    }
+
+    /// Dedicate one of the workers in this pool to running a sequence of jobs that share state that need not be
+    /// [`Send`], returning a handle that may be used to enqueue those jobs. The state is created on the worker with
+    /// the given function.
+    ///
+    /// This is an escape hatch for work that depends on a single-threaded resource. See [`PinnedWorker`] for more
+    /// information, including the constraints that this places on the rest of the pool.
+    pub fn run_pinned<S: 'static, I: FnOnce() -> S + Send + 'static>(&self, init: I) -> PinnedWorker<S> {
+        pinned::run(self, init)
+    }
+
+    /// Create a [`Strand`], which executes the jobs enqueued on it one at a time, in order, without dedicating a
+    /// worker to them.
+    pub fn strand(&self) -> Strand<'_> { Strand::new(self) }
+
+    /// Apply a function to each item of an iterator in parallel, returning an iterator over the results in the order
+    /// that they finish. See [`ParMap`] for more information.
+    ///
+    /// Jobs are enqueued as the returned iterator is advanced, so nothing happens until then. Since the iterator waits
+    /// on jobs in this pool, advancing it from a job running on a pool with a single thread will deadlock.
+    ///
+    /// # Panics
+    ///
+    /// Advancing the returned iterator will panic if the function panicked for the item that it's waiting on.
+    pub fn par_map<I, F, R>(&self, iter: I, f: F) -> ParMap<'_, I::IntoIter, F, R>
+    where
+        I: IntoIterator,
+        I::Item: Send + 'static,
+        F: Fn(I::Item) -> R + Send + Sync + 'static,
+        R: Send + 'static,
+    {
+        ParMap::new(self, iter.into_iter(), f)
+    }
+
+    /// Apply a function to each item of an iterator in parallel, combining the results into a single value.
+    ///
+    /// The items are collected and split into contiguous chunks, one for each thread in the pool. Each chunk is folded
+    /// by a single job, starting from a clone of `identity`, and the results of the chunks are then combined in order
+    /// on the current thread. This means that:
+    ///
+    /// - `combine` must be associative (`combine(a, combine(b, c))` must equal `combine(combine(a, b), c)`), since
+    ///   the order in which results are grouped depends on the number of threads. It need not be commutative: results
+    ///   are always combined in the order of the items that they came from.
+    /// - `identity` must be an identity of `combine` (`combine(identity, x)` must equal `x`), since it's used as the
+    ///   starting point of every chunk. It is returned as-is if the iterator is empty.
+    ///
+    /// As with [`ThreadPool::par_map`], calling this from a job running on a pool with a single thread will deadlock.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// assert_eq!(pool.reduce(1..=100u64, 0, |x| x * x, |a, b| a + b), 338350);
+    /// assert_eq!(pool.reduce(vec![3, 7, 2], i32::MIN, |x| x, i32::max), 7);
+    ///
+    /// // Concatenation is associative but not commutative, so the order of the items is kept
+    /// let s = pool.reduce(0..10, String::new(), |x| x.to_string(), |a, b| a + &b);
+    /// assert_eq!(s, "0123456789");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `map` or `combine` panicked on any of the pool's threads.
+    pub fn reduce<I, T, F, G>(&self, items: I, identity: T, map: F, combine: G) -> T
+    where
+        I: IntoIterator,
+        I::Item: Send + 'static,
+        T: Clone + Send + 'static,
+        F: Fn(I::Item) -> T + Send + Sync + 'static,
+        G: Fn(T, T) -> T + Send + Sync + 'static,
+    {
+        par::reduce(self, items, identity, map, combine)
+    }
+
+    // Create a job from a function, giving it an identifier and applying the job wrappers of the pool (with the first
+    // wrapper to be registered being the outermost)
+    fn job(&self, name: Option<Arc<str>>, f: Box<dyn FnOnce() + Send>) -> Job {
+        Job {
+            id: self.shared.next_job_id.fetch_add(1, Ordering::Relaxed),
+            name,
+            f: self.shared.config.job_wrappers.iter().rev().fold(f, |f, wrapper| wrapper(f)),
+        }
+    }
+
+    // Returns an identifier that is unique to this pool for as long as it exists
+    fn id(&self) -> usize { Arc::as_ptr(&self.shared) as usize }
+
+    // Returns another handle to this pool. The queue stays open until every handle has been dropped.
+    #[cfg(feature = "recv")]
+    fn share(&self) -> Self { Self { tx: self.tx.clone(), shared: self.shared.clone() } }
+
+    // Returns whether the current thread is one of this pool's workers
+    pub(crate) fn is_current_worker(&self) -> bool { worker::current().is_some_and(|(id, _)| id == self.id()) }
+
+    // If a job is waiting in the queue, execute it on the current thread. Returns whether a job was executed.
+    pub(crate) fn run_one_pending(&self) -> bool {
+        match self.shared.rx.try_recv() {
+            Ok(job) => {
+                worker::execute(&self.shared, job);
+                true
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// Create a new [`JobGroup`], allowing a logical group of jobs to be waited upon independently of other work on
+    /// the pool.
+    pub fn group(&self) -> JobGroup<'_> {
+        JobGroup::new(self)
+    }
+
+    /// Execute jobs waiting in the queue on the current thread until the queue is empty, returning how many were
+    /// executed.
+    ///
+    /// This lets a thread help to clear a backlog (for example, before shutting the pool down) rather than waiting for
+    /// the workers to get through it. Workers keep taking jobs from the queue in the meantime, but each job is only
+    /// ever executed once. Panics are handled according to the pool's [`PanicPolicy`], just as with jobs executed by
+    /// the pool.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(1).finish().unwrap();
+    ///
+    /// // Keep the only worker busy
+    /// let (tx, rx) = std::sync::mpsc::channel::<()>();
+    /// pool.run(move || rx.recv().unwrap());
+    /// while pool.active_jobs() == 0 { std::thread::yield_now(); }
+    ///
+    /// for i in 0..10 {
+    ///     pool.run(move || println!("I am the {}th job!", i));
+    /// }
+    /// assert_eq!(pool.run_pending_here(), 10);
+    /// tx.send(()).unwrap();
+    /// ```
+    pub fn run_pending_here(&self) -> usize {
+        let mut executed = 0;
+        while self.run_one_pending() {
+            executed += 1;
+        }
+        executed
+    }
+
+    /// Signal to threads (not jobs) that they should stop, then wait for them to finish processing jobs.
+    ///
+    /// All outstanding jobs will be executed before this function returns. If the pool is paused, it is resumed.
+    ///
+    /// If a worker thread panicked (which only happens if the pool was configured with
+    /// [`ThreadPoolBuilder::with_catch_panics`]), the payload of the first such panic is returned once every worker
+    /// has finished.
+    pub fn join_all(self) -> thread::Result<()> {
+        self.stop(ShutdownMode::Drain).expect("draining the pool without a timeout timed out")
+    }
+
+    /// Shut down the pool, treating queued jobs according to the given [`ShutdownMode`], then wait for its threads to
+    /// finish. If the pool is paused, it is resumed.
+    ///
+    /// [`ShutdownMode::Drain`] behaves just like [`ThreadPool::join_all`], except that the payload of a worker that
+    /// panicked is returned as [`Error::Panicked`]. If [`ShutdownMode::DrainTimeout`] times out, [`Error::Timeout`]
+    /// is returned and the pool's threads are abandoned: they keep executing the remaining jobs in the background, and
+    /// stop once the queue is empty. The hook given to [`ThreadPoolBuilder::on_shutdown`] is only invoked once every
+    /// thread has stopped, so it is not invoked in this case.
+    ///
+    /// ```
+    /// use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    ///
+    /// // Notifies the channel when a job is dropped, whether or not it was executed
+    /// struct Guard(std::sync::mpsc::Sender<()>);
+    /// impl Drop for Guard {
+    ///     fn drop(&mut self) { self.0.send(()).unwrap(); }
+    /// }
+    ///
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(1).finish().unwrap();
+    ///
+    /// // Occupy the only worker until the other jobs have been dropped, so that none of them can be executed
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// pool.run(move || for _ in 0..10 { rx.recv().unwrap() });
+    /// while pool.queue_len() > 0 { std::thread::yield_now(); }
+    ///
+    /// let executed = Arc::new(AtomicUsize::new(0));
+    /// for _ in 0..10 {
+    ///     let (executed, guard) = (executed.clone(), Guard(tx.clone()));
+    ///     pool.run(move || { let _guard = guard; executed.fetch_add(1, Ordering::SeqCst); });
+    /// }
+    ///
+    /// pool.shutdown(lagoon::ShutdownMode::Cancel).unwrap();
+    /// assert_eq!(executed.load(Ordering::SeqCst), 0);
+    /// ```
+    pub fn shutdown(self, mode: ShutdownMode) -> Result<(), Error> {
+        match self.stop(mode) {
+            Some(Ok(())) => Ok(()),
+            Some(Err(payload)) => Err(Error::Panicked(Panic::new(payload))),
+            None => Err(Error::Timeout),
+        }
+    }
+
+    // Shut down the pool and join its threads, returning `None` if they didn't finish within the mode's timeout
+    fn stop(self, mode: ShutdownMode) -> Option<thread::Result<()>> {
+        if mode == ShutdownMode::Cancel {
+            // While the pool is owned here, no other thread can enqueue jobs behind our back (other than continuations
+            // of jobs that are still running, which are enqueued once they complete)
+            while let Some(job) = self.shared.rx.pop_oldest() {
+                drop(job);
+                self.shared.finish_job();
+            }
+        }
+        self.resume();
+        let Self { tx, shared, .. } = self;
+        drop(tx);
+        if let ShutdownMode::DrainTimeout(timeout) = mode {
+            let lock = shared.quiescent_lock.lock().unwrap();
+            let (_lock, res) = shared.quiescent
+                .wait_timeout_while(lock, timeout, |_| shared.outstanding.load(Ordering::Acquire) > 0)
+                .unwrap();
+            if res.timed_out() {
+                return None;
+            }
+        }
+        // Workers may be recycled while we're joining them, but a recycled worker always registers its replacement
+        // before it exits.
+        let mut res = Ok(());
+        while let Some(handle) = shared.handles.lock().unwrap().pop() {
+            // Keep joining the remaining workers even if one of them panicked
+            if let Err(payload) = handle.join() {
+                res = res.and(Err(payload));
+            }
+        }
+        let on_shutdown = shared.config.on_shutdown.as_ref().and_then(|f| f.lock().unwrap().take());
+        if let Some(on_shutdown) = on_shutdown {
+            let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(on_shutdown));
+        }
+        Some(res)
+    }
+
+    /// Create a scope that allows the spawning of threads with safe access to the current scope.
+    ///
+    /// This function will wait for all jobs created in the scope to finish before continuing. See [`Scope`] for more
+    /// information about scoped jobs.
+    ///
+    /// If this function is called from within a job running on this pool, the worker will execute other queued jobs
+    /// while it waits rather than sitting idle. This means that nested scopes cannot deadlock the pool, even when every
+    /// worker is waiting on one.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(1).finish().unwrap();
+    ///
+    /// let mut x = 0;
+    /// pool.scoped(|s| {
+    ///     // The only worker waits on a nested scope, so it must run the inner job itself
+    ///     s.run(|| pool.scoped(|s| s.run(|| x += 1)));
+    /// });
+    /// assert_eq!(x, 1);
+    /// ```
+    #[cfg(feature = "scope")]
+    pub fn scoped<'pool, 'scope, F: FnOnce(scope::Scope<'pool, 'scope>) -> R, R>(&'pool self, f: F) -> R {
+        scope::run(self, f)
+    }
+
+    /// Create a scope like [`ThreadPool::scoped`], temporarily adding the given number of extra workers to the pool
+    /// for as long as the scope exists.
+    ///
+    /// This speeds up bursts of heavy work without permanently oversizing the pool. The temporary workers execute jobs
+    /// from the same queue as the pool's own workers (including jobs enqueued outside of the scope), and retire once
+    /// the scope's jobs have finished, without disturbing the pool's own workers. A temporary worker that is executing
+    /// a job when the scope ends finishes it first, so this may also wait for jobs that were enqueued outside of the
+    /// scope. Temporary workers are spawned like the pool's own workers (with the same name, stack size and spawner,
+    /// although the spawner is given indices at or above [`ThreadPool::thread_count`] and [`ThreadConfig::temporary`]
+    /// is set), but they don't run the pool's hooks or have worker state, and aren't counted by
+    /// [`ThreadPool::thread_count`]. If a temporary worker can't be spawned, the scope makes do without it.
+    ///
+    /// Temporary workers count as workers of the pool, so they help out with queued jobs while waiting on a nested
+    /// scope, but they have no index: [`current_worker_index`] returns `None` on them.
+    ///
+    /// ```
+    /// use std::sync::{atomic::{AtomicBool, Ordering}, Barrier};
+    ///
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(1).finish().unwrap();
+    ///
+    /// // Every job waits for all of the others, so they can only finish if they run at the same time
+    /// let barrier = Barrier::new(4);
+    /// pool.scoped_with_threads(3, |s| {
+    ///     for _ in 0..4 {
+    ///         s.run(|| { barrier.wait(); });
+    ///     }
+    /// });
+    /// assert_eq!(pool.thread_count(), 1);
+    ///
+    /// // One job waits on a nested scope while the other blocks, so whichever worker runs the first job (temporary or
+    /// // not) must run the nested job itself
+    /// let (started, finished, nested) = (Barrier::new(2), Barrier::new(2), AtomicBool::new(false));
+    /// pool.scoped_with_threads(1, |s| {
+    ///     for _ in 0..2 {
+    ///         s.run(|| {
+    ///             started.wait();
+    ///             if !nested.swap(true, Ordering::SeqCst) {
+    ///                 pool.scoped(|s| s.run(|| { finished.wait(); }));
+    ///             } else {
+    ///                 finished.wait();
+    ///             }
+    ///         });
+    ///     }
+    /// });
+    /// ```
+    #[cfg(feature = "scope")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "scope")))]
+    pub fn scoped_with_threads<'pool, 'scope, F, R>(&'pool self, extra: usize, f: F) -> R
+    where
+        F: FnOnce(scope::Scope<'pool, 'scope>) -> R,
+    {
+        let retire = Arc::new(AtomicBool::new(false));
+        let temporary = (0..extra)
+            .filter_map(|i| worker::spawn_temporary(&self.shared, self.thread_count() + i, retire.clone()).ok())
+            .collect::<Vec<_>>();
+        // Retire the temporary workers once the scope's jobs have finished, even if `f` panics
+        let _guard = scopeguard::guard(temporary, |temporary| {
+            retire.store(true, Ordering::Release);
+            self.shared.temporaries.notify_retired();
+            for worker in temporary {
+                let _ = worker.join();
+            }
+        });
+        self.scoped(f)
+    }
+
+    /// Create a scope like [`ThreadPool::scoped`], but give up waiting for the jobs created in it if they have not all
+    /// finished within the given timeout, returning [`Error::Timeout`].
+    ///
+    /// Jobs can't be stopped once they've started, so any jobs that are still running (or queued) when the timeout
+    /// elapses keep running detached from the scope. For this to be sound, jobs may only borrow data that lives for
+    /// `'static`, which is why the scope has a `'static` lifetime: jobs may not borrow from the caller's stack. Shared
+    /// state should instead be moved into the jobs behind an [`Arc`] (and so must be [`Sync`]), or be a `static`.
+    /// This still has advantages over [`ThreadPool::run`], since all of the scope's features (such as
+    /// [`Scope::with_local`]) remain available, and waiting is taken care of.
+    ///
+    /// If the function panics, the jobs that it created are not waited for.
+    ///
+    /// ```
+    /// use std::{sync::{Arc, atomic::{AtomicUsize, Ordering}}, time::Duration};
+    ///
+    /// let pool = lagoon::ThreadPool::default();
+    /// let count = Arc::new(AtomicUsize::new(0));
+    ///
+    /// let res = pool.scoped_timeout(Duration::from_secs(10), |s| {
+    ///     for _ in 0..10 {
+    ///         let count = count.clone();
+    ///         s.run(move || { count.fetch_add(1, Ordering::Relaxed); });
+    ///     }
+    /// });
+    /// assert!(res.is_ok());
+    /// assert_eq!(count.load(Ordering::Relaxed), 10);
+    ///
+    /// let res = pool.scoped_timeout(Duration::from_millis(10), |s| {
+    ///     s.run(|| std::thread::sleep(Duration::from_secs(1)));
+    /// });
+    /// assert!(matches!(res, Err(lagoon::Error::Timeout)));
+    /// ```
+    #[cfg(feature = "scope")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "scope")))]
+    pub fn scoped_timeout<'pool, F, R>(&'pool self, timeout: Duration, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(scope::Scope<'pool, 'static>) -> R,
+    {
+        scope::run_timeout(self, timeout, f)
+    }
+
+    /// Create a scope that allows the spawning of jobs with safe access to the current scope, returning the results of
+    /// every job spawned within it in the order that they were spawned.
+    ///
+    /// This function will wait for all jobs created in the scope to finish before continuing. See [`CollectScope`] for
+    /// more information.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if any job spawned within the scope panicked, since its result is unavailable.
+    #[cfg(feature = "scope")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "scope")))]
+    pub fn scoped_collect<'pool, 'scope, F, R>(&'pool self, f: F) -> Vec<R>
+    where
+        F: FnOnce(&scope::CollectScope<'pool, 'scope, R>),
+        R: Send + 'scope,
+    {
+        scope::run_collect(self, f)
+    }
+
+    /// Create a scope that allows the spawning of fallible jobs with safe access to the current scope, returning the
+    /// first error that occurs in any of them.
+    ///
+    /// Once a job has failed, the remaining jobs are skipped. This function still waits for any jobs that were already
+    /// running to finish before returning. See [`TryScope`] for more information.
+    #[cfg(feature = "scope")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "scope")))]
+    pub fn scoped_try<'pool, 'scope, F, E>(&'pool self, f: F) -> Result<(), E>
+    where
+        F: FnOnce(&scope::TryScope<'pool, 'scope, E>),
+        E: Send + 'scope,
+    {
+        scope::run_try(self, f)
+    }
+}
+
+/// A type used to configure a [`ThreadPool`] prior to its creation.
+#[derive(Clone, Default)]
+pub struct ThreadPoolBuilder {
+    thread_count: Option<usize>,
+    reserved_cores: Option<usize>,
+    thread_count_fraction: Option<f32>,
+    physical_cores_only: bool,
+    env_override: Option<String>,
+    preset: Option<ThreadCountPreset>,
+    pool_name: Option<String>,
+    thread_name: Option<String>,
+    thread_stack_size: Option<usize>,
+    after_start: Option<WorkerHook>,
+    before_stop: Option<WorkerHook>,
+    on_shutdown: Option<ShutdownHook>,
+    warmup: Option<WarmupHook>,
+    prefault_stack: Option<usize>,
+    on_warning: Option<Arc<dyn Fn(Warning) + Send + Sync>>,
+    on_panic: Option<PanicHandler>,
+    on_job_start: Option<JobStartHook>,
+    on_job_end: Option<JobEndHook>,
+    on_queue_high_water: Option<(usize, QueueHook)>,
+    #[cfg(feature = "affinity")]
+    core_affinity: Option<Vec<usize>>,
+    #[cfg(feature = "affinity")]
+    affinity_strategy: Option<AffinityStrategy>,
+    #[cfg(feature = "priority")]
+    os_priority: Option<Priority>,
+    #[cfg(all(unix, feature = "realtime"))]
+    realtime_policy: Option<RtPolicy>,
+    #[cfg(feature = "qos")]
+    qos_class: Option<QosClass>,
+    #[cfg(feature = "ioprio")]
+    io_priority: Option<IoPriority>,
+    #[cfg(feature = "numa")]
+    numa_placement: Option<os::NumaPlacement>,
+    #[cfg(feature = "rand")]
+    rng_seed: Option<u64>,
+    recycle_after_jobs: Option<usize>,
+    recycle_after_age: Option<Duration>,
+    scheduling: Scheduling,
+    idle_strategy: IdleStrategy,
+    queue_capacity: Option<usize>,
+    queue: Option<QueueInit>,
+    full_queue_policy: FullQueuePolicy,
+    on_job_evicted: Option<EvictHook>,
+    inline_overflow: Option<usize>,
+    panic_policy: PanicPolicy,
+    catch_panics: Option<bool>,
+    job_wrappers: Vec<JobWrapper>,
+    worker_state: Option<WorkerStateInit>,
+    spawn_mode: SpawnMode,
+    deterministic: bool,
+    spawner: Option<Spawner>,
+}
+
+// Hooks can't be formatted, so only their presence is shown
+impl fmt::Debug for ThreadPoolBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("ThreadPoolBuilder");
+        s
+            .field("thread_count", &self.thread_count)
+            .field("reserved_cores", &self.reserved_cores)
+            .field("thread_count_fraction", &self.thread_count_fraction)
+            .field("physical_cores_only", &self.physical_cores_only)
+            .field("env_override", &self.env_override)
+            .field("preset", &self.preset)
+            .field("pool_name", &self.pool_name)
+            .field("thread_name", &self.thread_name)
+            .field("thread_stack_size", &self.thread_stack_size)
+            .field("after_start", &self.after_start.is_some())
+            .field("before_stop", &self.before_stop.is_some())
+            .field("on_shutdown", &self.on_shutdown.is_some())
+            .field("warmup", &self.warmup.is_some())
+            .field("prefault_stack", &self.prefault_stack)
+            .field("on_warning", &self.on_warning.is_some())
+            .field("on_panic", &self.on_panic.is_some())
+            .field("on_job_start", &self.on_job_start.is_some())
+            .field("on_job_end", &self.on_job_end.is_some())
+            .field("on_queue_high_water", &self.on_queue_high_water.as_ref().map(|(threshold, _)| threshold));
+        #[cfg(feature = "affinity")]
+        s
+            .field("core_affinity", &self.core_affinity)
+            .field("affinity_strategy", &self.affinity_strategy);
+        #[cfg(feature = "priority")]
+        s.field("os_priority", &self.os_priority);
+        #[cfg(all(unix, feature = "realtime"))]
+        s.field("realtime_policy", &self.realtime_policy);
+        #[cfg(feature = "qos")]
+        s.field("qos_class", &self.qos_class);
+        #[cfg(feature = "ioprio")]
+        s.field("io_priority", &self.io_priority);
+        #[cfg(feature = "numa")]
+        s.field("numa_placement", &self.numa_placement);
+        #[cfg(feature = "rand")]
+        s.field("rng_seed", &self.rng_seed);
+        s
+            .field("recycle_after_jobs", &self.recycle_after_jobs)
+            .field("recycle_after_age", &self.recycle_after_age)
+            .field("scheduling", &self.scheduling)
+            .field("idle_strategy", &self.idle_strategy)
+            .field("queue_capacity", &self.queue_capacity)
+            .field("queue", &self.queue.is_some())
+            .field("full_queue_policy", &self.full_queue_policy)
+            .field("inline_overflow", &self.inline_overflow)
+            .field("on_job_evicted", &self.on_job_evicted.is_some())
+            .field("panic_policy", &self.panic_policy)
+            .field("catch_panics", &self.catch_panics)
+            .field("job_wrappers", &self.job_wrappers.len())
+            .field("worker_state", &self.worker_state.is_some())
+            .field("spawn_mode", &self.spawn_mode)
+            .field("deterministic", &self.deterministic)
+            .field("spawner", &self.spawner.is_some())
+            .finish()
+    }
+}
+
+impl ThreadPoolBuilder {
+    /// The number of threads per hardware thread used by [`ThreadPoolBuilder::for_blocking`].
+    pub const BLOCKING_THREADS_PER_CORE: usize = 4;
+
+    /// The smallest stack size accepted by [`ThreadPoolBuilder::with_thread_stack_size`].
+    pub const MIN_STACK_SIZE: usize = 16 * 1024;
+
+    /// Begin building a [`ThreadPool`] suited to CPU-bound work, with one thread per hardware thread minus one (with a
+    /// minimum of one thread). This leaves a core free for the thread that enqueues work, which usually has work of
+    /// its own to do.
+    ///
+    /// This is an opinionated starting point. The thread count is derived from the number of hardware threads (see
+    /// [`ThreadCountPreset::Compute`]) when the pool is finished, unless another way of sizing the pool is given (such
+    /// as [`ThreadPoolBuilder::with_thread_count`] or [`ThreadPoolBuilder::with_reserved_cores`]), in which case that
+    /// takes its place. Every other option may be changed as usual.
+    ///
+    /// ```
+    /// let detected = lagoon::available_concurrency().unwrap_or(lagoon::ThreadPool::DEFAULT_THREAD_COUNT);
+    ///
+    /// let pool = lagoon::ThreadPoolBuilder::for_compute().finish().unwrap();
+    /// assert_eq!(pool.thread_count(), lagoon::ThreadCountPreset::Compute.thread_count(detected));
+    ///
+    /// let pool = lagoon::ThreadPoolBuilder::for_compute().with_thread_count(3).finish().unwrap();
+    /// assert_eq!(pool.thread_count(), 3);
+    ///
+    /// let pool = lagoon::ThreadPoolBuilder::for_compute().with_reserved_cores(1).finish().unwrap();
+    /// assert_eq!(pool.thread_count(), detected.saturating_sub(1).max(1));
+    /// ```
+    pub fn for_compute() -> Self {
+        Self { preset: Some(ThreadCountPreset::Compute), ..Self::default() }
+    }
+
+    /// Begin building a [`ThreadPool`] suited to work that spends most of its time blocked, such as blocking IO, with
+    /// [`ThreadPoolBuilder::BLOCKING_THREADS_PER_CORE`] threads per hardware thread. Since many of these threads may
+    /// never be needed, they are spawned lazily (see [`ThreadPoolBuilder::spawn_lazily`]).
+    ///
+    /// As with [`ThreadPoolBuilder::for_compute`], the thread count is derived from the number of hardware threads
+    /// (see [`ThreadCountPreset::Blocking`]) unless another way of sizing the pool is given, and every other option
+    /// may be changed as usual.
+    ///
+    /// ```
+    /// let detected = lagoon::available_concurrency().unwrap_or(lagoon::ThreadPool::DEFAULT_THREAD_COUNT);
+    ///
+    /// let pool = lagoon::ThreadPoolBuilder::for_blocking().finish().unwrap();
+    /// assert_eq!(pool.thread_count(), lagoon::ThreadCountPreset::Blocking.thread_count(detected));
+    /// assert_eq!(pool.spawned_threads(), 0);
+    ///
+    /// let pool = lagoon::ThreadPoolBuilder::for_blocking().with_thread_count_fraction(2.0).finish().unwrap();
+    /// assert_eq!(pool.thread_count(), detected * 2);
+    /// ```
+    pub fn for_blocking() -> Self {
+        Self { preset: Some(ThreadCountPreset::Blocking), ..Self::default() }.spawn_lazily(true)
+    }
+
+    /// Configure the [`ThreadPool`] with the given number of threads. If unspecified, the thread pool will attempt to
+    /// detect the number of hardware threads available to the process and use that. If this also fails,
+    /// [`ThreadPool::DEFAULT_THREAD_COUNT`] will be used. The detected number may instead be scaled with
+    /// [`ThreadPoolBuilder::with_reserved_cores`] or [`ThreadPoolBuilder::with_thread_count_fraction`].
+    pub fn with_thread_count(self, thread_count: usize) -> Self {
+        Self { thread_count: Some(thread_count), ..self }
+    }
+
+    /// Configure the [`ThreadPool`] to leave the given number of cores free for other threads, using the detected
+    /// number of hardware threads (see [`ThreadPoolBuilder::with_thread_count`]) minus `n`, with a minimum of one
+    /// thread.
+    ///
+    /// This is useful for applications like games, where at least one core should be kept free at all times so that
+    /// the main thread runs smoothly. This option cannot be combined with an explicit thread count or a thread count
+    /// fraction: [`ThreadPoolBuilder::finish`] will return [`Error::InvalidConfig`] if more than one is given.
+    ///
+    /// ```
+    /// // Keep a core free for the main thread
+    /// let pool = lagoon::ThreadPool::global_with_builder(lagoon::ThreadPool::build().with_reserved_cores(1));
+    ///
+    /// let detected = lagoon::available_concurrency().unwrap_or(lagoon::ThreadPool::DEFAULT_THREAD_COUNT);
+    /// assert_eq!(pool.thread_count(), detected.saturating_sub(1).max(1));
+    /// ```
+    pub fn with_reserved_cores(self, n: usize) -> Self {
+        Self { reserved_cores: Some(n), ..self }
+    }
+
+    /// Configure the [`ThreadPool`] to use the given fraction of the detected number of hardware threads (see
+    /// [`ThreadPoolBuilder::with_thread_count`]), rounded to the nearest whole number (with halves rounded up) and
+    /// with a minimum of one thread. Fractions greater than `1.0` oversubscribe the machine.
+    ///
+    /// [`ThreadPoolBuilder::finish`] will return [`Error::InvalidConfig`] if the fraction is not finite and positive,
+    /// or if an explicit thread count or reserved cores are also given.
+    ///
+    /// ```
+    /// // Use half of the machine
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count_fraction(0.5)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let detected = lagoon::available_concurrency().unwrap_or(lagoon::ThreadPool::DEFAULT_THREAD_COUNT);
+    /// assert_eq!(pool.thread_count(), ((detected as f32 * 0.5).round() as usize).max(1));
+    ///
+    /// assert!(lagoon::ThreadPool::build().with_thread_count_fraction(-1.0).finish().is_err());
+    /// ```
+    pub fn with_thread_count_fraction(self, fraction: f32) -> Self {
+        Self { thread_count_fraction: Some(fraction), ..self }
+    }
+
+    /// Detect the number of threads for the [`ThreadPool`] by counting physical CPU cores only, rather than every
+    /// hardware thread (see [`available_physical_concurrency`]). By default, every hardware thread is counted.
+    ///
+    /// Workloads that are bound by memory bandwidth often gain nothing from simultaneous multithreading, so using one
+    /// worker per physical core avoids needless contention. This option has no effect when an explicit thread count
+    /// is given, but combines with [`ThreadPoolBuilder::with_reserved_cores`] and
+    /// [`ThreadPoolBuilder::with_thread_count_fraction`].
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_physical_cores_only(true)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(Some(pool.thread_count()), lagoon::available_physical_concurrency());
+    /// ```
+    pub fn with_physical_cores_only(self, physical_only: bool) -> Self {
+        Self { physical_cores_only: physical_only, ..self }
+    }
+
+    /// Allow the thread count of the [`ThreadPool`] to be overridden at runtime with the given environment variable.
+    ///
+    /// If the variable is set to a positive integer, that number of threads is used regardless of the other sizing
+    /// options. Otherwise, the thread count is determined as normal. If the variable is set to anything else, a
+    /// [`Warning::InvalidEnvOverride`] is emitted (see [`ThreadPoolBuilder::on_warning`]). This allows the pool to be
+    /// tuned for each deployment without rebuilding the program.
+    ///
+    /// Pools created with [`ThreadPool::default`] (including the global pool) honour [`ThreadPool::THREADS_ENV_VAR`].
+    ///
+    /// ```
+    /// std::env::set_var("MY_APP_THREADS", "3");
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(8)
+    ///     .with_env_override("MY_APP_THREADS")
+    ///     .finish()
+    ///     .unwrap();
+    /// assert_eq!(pool.thread_count(), 3);
+    ///
+    /// // Invalid values are ignored
+    /// std::env::set_var("MY_APP_THREADS", "lots");
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(8)
+    ///     .with_env_override("MY_APP_THREADS")
+    ///     .on_warning(|w| assert!(matches!(w, lagoon::Warning::InvalidEnvOverride { .. })))
+    ///     .finish()
+    ///     .unwrap();
+    /// assert_eq!(pool.thread_count(), 8);
+    ///
+    /// std::env::set_var(lagoon::ThreadPool::THREADS_ENV_VAR, "2");
+    /// assert_eq!(lagoon::ThreadPool::default().thread_count(), 2);
+    /// ```
+    pub fn with_env_override(self, var: &str) -> Self {
+        Self { env_override: Some(var.to_string()), ..self }
+    }
+
+    /// Give this [`ThreadPool`] a name, which distinguishes it from other pools in diagnostics (such as warnings and
+    /// the output of its [`Debug`] implementation) and may be retrieved with [`ThreadPool::name`].
+    ///
+    /// Unless they are given a name with [`ThreadPoolBuilder::with_thread_name`], the threads owned by the pool are
+    /// named after it, with the name of each being `{pool}-{index}`. This makes it possible to tell which pool a
+    /// thread belongs to in debuggers, profilers, and panic messages. The name may not contain NUL bytes.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_pool_name("indexer".to_string())
+    ///     .with_thread_count(2)
+    ///     .finish()
+    ///     .unwrap();
+    /// assert_eq!(pool.name(), Some("indexer"));
+    ///
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// pool.run(move || tx.send(std::thread::current().name().map(str::to_string)).unwrap());
+    /// assert!(rx.recv().unwrap().unwrap().starts_with("indexer-"));
+    /// ```
+    pub fn with_pool_name(self, name: String) -> Self {
+        Self { pool_name: Some(name), ..self }
+    }
+
+    /// Give the threads owned by this [`ThreadPool`] the given name. If unspecified, the default name will be the same
+    /// as those created by [`std::thread::spawn`], unless the pool has a name (see
+    /// [`ThreadPoolBuilder::with_pool_name`]).
+    ///
+    /// The name must be non-empty and may not contain NUL bytes, or [`ThreadPoolBuilder::finish`] will return
+    /// [`Error::InvalidThreadName`].
+    pub fn with_thread_name(self, name: String) -> Self {
+        Self { thread_name: Some(name), ..self }
+    }
+
+    /// Give the threads owned by this [`ThreadPool`] a specific stack size. If unspecified, the default stack size
+    /// will be the same as those created by [`std::thread::spawn`].
+    ///
+    /// The size must be at least [`ThreadPoolBuilder::MIN_STACK_SIZE`], or [`ThreadPoolBuilder::finish`] will return
+    /// [`Error::StackSizeTooSmall`].
+    ///
+    /// The standard library doesn't allow the size of the guard page below the stack to be configured, so it's left to
+    /// the platform. Overflowing the stack aborts the process, so deeply recursive jobs should check how much of the
+    /// stack is left with [`remaining_stack`] (which requires the stack size to be given here).
+    pub fn with_thread_stack_size(self, size: usize) -> Self {
+        Self { thread_stack_size: Some(size), ..self }
+    }
+
+    /// Invoke the given function on each worker thread immediately after it is spawned, before it begins executing
+    /// jobs. The function is given the index of the worker, from `0` to the number of threads in the pool.
+    ///
+    /// This is useful for initialising thread-local state, registering threads with a profiler, etc. If the function
+    /// panics, the panic will be reported by the panic hook as usual but the worker will continue to run.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(4)
+    ///     .after_start(|idx| println!("Worker {} started!", idx))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    pub fn after_start<F: Fn(usize) + Send + Sync + 'static>(self, f: F) -> Self {
+        Self { after_start: Some(Arc::new(f)), ..self }
+    }
+
+    /// Invoke the given function on each worker thread after it is spawned, before it begins executing jobs, to warm
+    /// it up. This is useful for avoiding latency in the first job executed by each worker, by initialising
+    /// thread-local state, filling caches, etc.
+    ///
+    /// Unlike [`ThreadPoolBuilder::after_start`], spawning workers (in [`ThreadPoolBuilder::finish`], or later for
+    /// pools that spawn their threads lazily) waits for every new worker to finish warming up, so the pool is warm once
+    /// it's returned. If the function panics, the panic will be reported by the panic hook as usual but the worker will
+    /// continue to run.
+    ///
+    /// ```
+    /// use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    ///
+    /// let warmed = Arc::new(AtomicUsize::new(0));
+    /// let warmed2 = warmed.clone();
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(4)
+    ///     .with_warmup(move || { warmed2.fetch_add(1, Ordering::Relaxed); })
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(warmed.load(Ordering::Relaxed), 4);
+    /// ```
+    pub fn with_warmup<F: Fn() + Send + Sync + 'static>(self, f: F) -> Self {
+        Self { warmup: Some(Arc::new(f)), ..self }
+    }
+
+    /// Touch the given number of bytes of each worker thread's stack after it is spawned, before it begins executing
+    /// jobs, so that the memory is faulted in ahead of time rather than during the first jobs that need it. As with
+    /// [`ThreadPoolBuilder::with_warmup`], spawning workers waits for this to finish.
+    ///
+    /// The number of bytes must be less than the stack size of the worker threads. If the stack size was given to
+    /// [`ThreadPoolBuilder::with_thread_stack_size`], [`ThreadPoolBuilder::finish`] returns [`Error::InvalidConfig`]
+    /// if there would be less than [`ThreadPoolBuilder::MIN_STACK_SIZE`] bytes of the stack to spare.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_stack_size(8 * 1024 * 1024)
+    ///     .with_prefault_stack(4 * 1024 * 1024)
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    pub fn with_prefault_stack(self, bytes: usize) -> Self {
+        Self { prefault_stack: Some(bytes), ..self }
+    }
+
+    /// Invoke the given function on each worker thread immediately before it stops, after it has finished executing
+    /// jobs. The function is given the index of the worker, from `0` to the number of threads in the pool.
+    ///
+    /// This is useful for tearing down thread-local state initialised with [`ThreadPoolBuilder::after_start`]. If the
+    /// function panics, the panic will be reported by the panic hook as usual and the worker will still exit cleanly.
+    pub fn before_stop<F: Fn(usize) + Send + Sync + 'static>(self, f: F) -> Self {
+        Self { before_stop: Some(Arc::new(f)), ..self }
+    }
+
+    /// Invoke the given function once when the pool is shut down by [`ThreadPool::join_all`], after every worker has
+    /// stopped. This is useful for releasing resources used by the whole pool, such as flushing metrics.
+    ///
+    /// Unlike [`ThreadPoolBuilder::before_stop`], which runs on every worker, the function runs exactly once, on the
+    /// thread that called [`ThreadPool::join_all`]. It is not invoked if the pool is dropped without being joined.
+    /// Since the function can only be invoked once, pools created from clones of this builder (including by
+    /// [`ThreadPool::to_builder`]) share it: it is invoked when the first of them is joined. If the function panics,
+    /// the panic will be reported by the panic hook as usual and [`ThreadPool::join_all`] will still return normally.
+    ///
+    /// ```
+    /// use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    ///
+    /// let executed = Arc::new(AtomicUsize::new(0));
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .on_shutdown({
+    ///         let executed = executed.clone();
+    ///         move || tx.send(executed.load(Ordering::SeqCst)).unwrap()
+    ///     })
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// for _ in 0..10 {
+    ///     let executed = executed.clone();
+    ///     pool.run(move || { executed.fetch_add(1, Ordering::SeqCst); });
+    /// }
+    /// pool.join_all().unwrap();
+    ///
+    /// // Every job had finished by the time the function was invoked
+    /// assert_eq!(rx.try_recv(), Ok(10));
+    /// ```
+    pub fn on_shutdown<F: FnOnce() + Send + 'static>(self, f: F) -> Self {
+        Self { on_shutdown: Some(Arc::new(Mutex::new(Some(Box::new(f))))), ..self }
+    }
+
+    /// Periodically replace worker threads with fresh ones, once they have executed `after_jobs` jobs or have been
+    /// alive for `after_age`, whichever comes first. If both are `None`, workers are never replaced (the default).
+    ///
+    /// Long-lived threads can accumulate thread-local caches and allocator fragmentation from the jobs they run.
+    /// Recycling them keeps memory usage flat. A worker that is due to be recycled spawns its replacement (with the
+    /// same attributes and hooks) before exiting, so no queued jobs are lost and concurrency is only reduced for the
+    /// time it takes the replacement to start. The number of replacements may be queried with
+    /// [`ThreadPool::threads_recycled`].
+    ///
+    /// If a replacement cannot be spawned, the worker continues running and a [`Warning::Recycle`] is emitted (see
+    /// [`ThreadPoolBuilder::on_warning`]).
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(1)
+    ///     .with_thread_recycling(Some(10), None)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let group = pool.group();
+    /// for _ in 0..100 {
+    ///     group.run(|| {});
+    /// }
+    /// group.wait();
+    /// # while pool.threads_recycled() < 10 { std::thread::yield_now(); }
+    /// assert_eq!(pool.threads_recycled(), 10);
+    /// ```
+    pub fn with_thread_recycling(self, after_jobs: Option<usize>, after_age: Option<Duration>) -> Self {
+        Self { recycle_after_jobs: after_jobs, recycle_after_age: after_age, ..self }
+    }
+
+    /// Spawn the threads owned by this [`ThreadPool`] with the given function rather than with
+    /// [`std::thread::Builder`] directly. If unspecified, threads are spawned with [`ThreadConfig::builder`].
+    ///
+    /// The function is given the attributes that the thread would otherwise have been given, along with the function
+    /// that the thread must run (the worker loop). It must either run that function on a new thread and return a handle
+    /// to it, or return an error. This is useful for integrating with runtimes that need to know about every thread,
+    /// crash handlers, sandboxes, test harnesses, etc.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_spawner(|config, f| {
+    ///         println!("Spawning worker {}", config.index);
+    ///         config.builder().spawn(f)
+    ///     })
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    pub fn with_spawner<F>(self, spawner: F) -> Self
+    where
+        F: Fn(ThreadConfig, Box<dyn FnOnce() + Send>) -> io::Result<JoinHandle<()>> + Send + Sync + 'static,
+    {
+        Self { spawner: Some(Arc::new(spawner)), ..self }
+    }
+
+    /// Configure when the threads of the [`ThreadPool`] are spawned. By default, they are all spawned in
+    /// [`ThreadPoolBuilder::finish`] ([`SpawnMode::Eager`]).
+    ///
+    /// Spawning threads lazily avoids wasting memory and startup time on pools that may go unused
+    /// ([`SpawnMode::OnFirstJob`]), or that only ever see a trickle of jobs and so only need as many threads as it
+    /// takes to keep up ([`SpawnMode::OnDemand`]). Either way, [`ThreadPool::thread_count`] reports the configured
+    /// number of threads, [`ThreadPool::spawned_threads`] reports the number spawned so far, and [`ThreadPool::ready`]
+    /// spawns every remaining thread at once. Errors that occur while spawning threads are returned by
+    /// [`ThreadPool::try_run`] and [`ThreadPool::ready`] instead of by `finish`.
+    ///
+    /// ```
+    /// use lagoon::SpawnMode;
+    ///
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(4)
+    ///     .with_spawn_mode(SpawnMode::OnFirstJob)
+    ///     .finish()
+    ///     .unwrap();
+    /// assert_eq!(pool.spawned_threads(), 0);
+    /// pool.run(|| println!("Hello!"));
+    /// assert_eq!(pool.spawned_threads(), 4);
+    ///
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(4)
+    ///     .with_spawn_mode(SpawnMode::OnDemand)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// // A single job at a time only needs a single thread
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// for _ in 0..10 {
+    ///     let tx = tx.clone();
+    ///     pool.run(move || tx.send(()).unwrap());
+    ///     rx.recv().unwrap();
+    ///     while pool.idle_workers() == 0 { std::thread::yield_now(); }
+    /// }
+    /// assert_eq!(pool.spawned_threads(), 1);
+    /// assert_eq!(pool.thread_count(), 4);
+    /// ```
+    pub fn with_spawn_mode(self, spawn_mode: SpawnMode) -> Self {
+        Self { spawn_mode, ..self }
+    }
+
+    /// Defer spawning the threads of the [`ThreadPool`] until the first job is enqueued, rather than spawning them in
+    /// [`ThreadPoolBuilder::finish`]. This is shorthand for [`ThreadPoolBuilder::with_spawn_mode`] with
+    /// [`SpawnMode::OnFirstJob`], or [`SpawnMode::Eager`] (the default) if `lazy` is `false`.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(4)
+    ///     .spawn_lazily(true)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(pool.config().spawn_mode, lagoon::SpawnMode::OnFirstJob);
+    /// assert_eq!(pool.spawned_threads(), 0);
+    /// ```
+    pub fn spawn_lazily(self, lazy: bool) -> Self {
+        self.with_spawn_mode(if lazy { SpawnMode::OnFirstJob } else { SpawnMode::Eager })
+    }
+
+    /// Spawn the threads of the [`ThreadPool`] one at a time as jobs arrive and find no idle worker, up to the
+    /// configured thread count, rather than spawning them in [`ThreadPoolBuilder::finish`]. This is shorthand for
+    /// [`ThreadPoolBuilder::with_spawn_mode`] with [`SpawnMode::OnDemand`], or [`SpawnMode::Eager`] (the default) if
+    /// `lazy` is `false`.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(4)
+    ///     .with_lazy_spawn(true)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(pool.config().spawn_mode, lagoon::SpawnMode::OnDemand);
+    /// assert_eq!(pool.spawned_threads(), 0);
+    /// pool.run(|| {});
+    /// assert_eq!(pool.spawned_threads(), 1);
+    /// ```
+    pub fn with_lazy_spawn(self, lazy: bool) -> Self {
+        self.with_spawn_mode(if lazy { SpawnMode::OnDemand } else { SpawnMode::Eager })
+    }
+
+    /// Configure the order in which the [`ThreadPool`] executes queued jobs. If unspecified, jobs are executed in the
+    /// order that they were enqueued ([`Scheduling::Fifo`]).
+    ///
+    /// See [`Scheduling`] for the trade-offs involved. Scoped jobs and job handles work identically under either mode.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(1)
+    ///     .with_scheduling(lagoon::Scheduling::Lifo)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// // Occupy the only worker so that all of the jobs are queued before any run
+    /// let (tx, rx) = std::sync::mpsc::channel::<()>();
+    /// pool.run(move || rx.recv().unwrap());
+    /// while pool.queue_len() > 0 { std::thread::yield_now(); }
+    ///
+    /// let order = Arc::new(Mutex::new(Vec::new()));
+    /// for i in 0..3 {
+    ///     let order = order.clone();
+    ///     pool.run(move || order.lock().unwrap().push(i));
+    /// }
+    /// tx.send(()).unwrap();
+    /// pool.join_all().unwrap();
+    ///
+    /// assert_eq!(*order.lock().unwrap(), vec![2, 1, 0]);
+    /// ```
+    pub fn with_scheduling(self, scheduling: Scheduling) -> Self {
+        Self { scheduling, ..self }
+    }
+
+    /// Configure what the workers of the [`ThreadPool`] do when they run out of jobs. If unspecified, workers block
+    /// immediately ([`IdleStrategy::Park`]).
+    ///
+    /// See [`IdleStrategy`] for the trade-offs involved.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_idle_strategy(lagoon::IdleStrategy::SpinThenPark { spins: 1000 })
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// for i in 0..10 {
+    ///     let tx = tx.clone();
+    ///     pool.run(move || tx.send(i).unwrap());
+    /// }
+    /// drop(tx);
+    ///
+    /// assert_eq!(rx.iter().sum::<i32>(), 45);
+    /// ```
+    pub fn with_idle_strategy(self, idle_strategy: IdleStrategy) -> Self {
+        Self { idle_strategy, ..self }
+    }
+
+    /// Have idle workers check the queue the given number of times, with a spin-loop hint between each check, before
+    /// blocking. This is shorthand for [`IdleStrategy::SpinThenPark`], or [`IdleStrategy::Park`] (the default) if
+    /// `spins` is `0`.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build().with_idle_spin(1000).finish().unwrap();
+    /// assert_eq!(pool.config().idle_strategy, lagoon::IdleStrategy::SpinThenPark { spins: 1000 });
+    /// ```
+    pub fn with_idle_spin(self, spins: usize) -> Self {
+        self.with_idle_strategy(match spins {
+            0 => IdleStrategy::Park,
+            spins => IdleStrategy::SpinThenPark { spins },
         })
     }
+
+    /// Limit the number of jobs that may be waiting in the queue of the [`ThreadPool`] at once. If unspecified, the
+    /// queue is unbounded.
+    ///
+    /// When the queue is full, enqueueing another job blocks the caller until a worker takes a job from the queue
+    /// (unless a different [`FullQueuePolicy`] is given to [`ThreadPoolBuilder::with_full_queue_policy`]). This
+    /// provides backpressure for pipelines in which producers may outpace the pool. The time spent blocking may
+    /// be measured with [`ThreadPool::run_blocking_timed`]. Take care when enqueueing jobs from within jobs: if every
+    /// worker blocks on a full queue, the pool will deadlock. A capacity of zero is rejected by
+    /// [`ThreadPoolBuilder::finish`] with [`Error::InvalidConfig`].
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_queue_capacity(16)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// for i in 0..1000 {
+    ///     pool.run(move || println!("I am the {}th job!", i));
+    /// }
+    /// ```
+    pub fn with_queue_capacity(self, capacity: usize) -> Self {
+        Self { queue_capacity: Some(capacity), ..self }
+    }
+
+    /// Decide what happens when a job is enqueued while the queue is full. See [`FullQueuePolicy`] for the available
+    /// policies. If unspecified, the caller blocks until there is space in the queue.
+    ///
+    /// This only has an effect if the queue has a capacity (see [`ThreadPoolBuilder::with_queue_capacity`]), and
+    /// can't be combined with a custom [`JobQueue`].
+    ///
+    /// ```
+    /// use std::sync::{Arc, Barrier, Mutex};
+    /// use lagoon::{FullQueuePolicy, ThreadPool};
+    ///
+    /// // Create a pool with the given policy, with its only worker blocked and a full queue
+    /// let full_pool = |policy, evicted: Arc<Mutex<Vec<usize>>>| {
+    ///     let pool = ThreadPool::build()
+    ///         .with_thread_count(1)
+    ///         .with_queue_capacity(1)
+    ///         .with_full_queue_policy(policy)
+    ///         .on_job_evicted(move |job| evicted.lock().unwrap().push(job.id()))
+    ///         .finish()
+    ///         .unwrap();
+    ///     let barrier = Arc::new(Barrier::new(2));
+    ///     let worker_barrier = barrier.clone();
+    ///     pool.run(move || { worker_barrier.wait(); });
+    ///     while pool.queue_len() > 0 { std::thread::yield_now(); }
+    ///     pool.run(|| {});
+    ///     (pool, barrier)
+    /// };
+    ///
+    /// // `Fail` rejects the job
+    /// let (pool, barrier) = full_pool(FullQueuePolicy::Fail, Default::default());
+    /// assert!(matches!(pool.try_run(|| {}), Err(lagoon::Error::QueueFull)));
+    /// barrier.wait();
+    ///
+    /// // `RunInline` executes the job on this thread, along with any jobs that it enqueues
+    /// let (pool, barrier) = full_pool(FullQueuePolicy::RunInline, Default::default());
+    /// let pool = Arc::new(pool);
+    /// let this = std::thread::current().id();
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// let inner_pool = pool.clone();
+    /// pool.run(move || {
+    ///     let tx2 = tx.clone();
+    ///     inner_pool.run(move || tx2.send(std::thread::current().id()).unwrap());
+    ///     tx.send(std::thread::current().id()).unwrap();
+    /// });
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![this, this]);
+    /// barrier.wait();
+    ///
+    /// // `DropOldest` evicts the queued job to make space
+    /// let evicted = Arc::new(Mutex::new(Vec::new()));
+    /// let (pool, barrier) = full_pool(FullQueuePolicy::DropOldest, evicted.clone());
+    /// pool.run(|| {});
+    /// assert_eq!(*evicted.lock().unwrap(), vec![1]);
+    /// assert_eq!(pool.queue_len(), 1);
+    /// barrier.wait();
+    /// ```
+    pub fn with_full_queue_policy(self, policy: FullQueuePolicy) -> Self {
+        Self { full_queue_policy: policy, ..self }
+    }
+
+    /// Execute jobs on the thread that enqueues them, rather than enqueueing them, while more than the given number of
+    /// jobs are waiting in the queue.
+    ///
+    /// This is like calling [`ThreadPool::run_or_inline`] for every job, but with a threshold of your choosing, and
+    /// applies to every way of enqueueing a job. It bounds the latency of jobs when the pool is overloaded, at the cost
+    /// of blocking the caller while the job runs: it is not suitable if jobs are enqueued while holding locks that the
+    /// jobs themselves (or other jobs) may need, since executing a job inline could then deadlock. This can't be
+    /// combined with [`ThreadPoolBuilder::with_deterministic`].
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(1)
+    ///     .with_inline_overflow(0)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// // Occupy the only worker, then fill the queue past the threshold
+    /// let (tx, rx) = std::sync::mpsc::channel::<()>();
+    /// pool.run(move || rx.recv().unwrap());
+    /// while pool.queue_len() > 0 { std::thread::yield_now(); }
+    /// pool.run(|| {});
+    ///
+    /// // The queue is over the threshold, so this job is executed immediately on this thread
+    /// let caller = std::thread::current().id();
+    /// let (id_tx, id_rx) = std::sync::mpsc::channel();
+    /// pool.run(move || id_tx.send(std::thread::current().id()).unwrap());
+    /// assert_eq!(id_rx.try_recv(), Ok(caller));
+    /// tx.send(()).unwrap();
+    /// ```
+    pub fn with_inline_overflow(self, threshold: usize) -> Self {
+        Self { inline_overflow: Some(threshold), ..self }
+    }
+
+    /// Invoke the given function with each job that is evicted from the queue by [`FullQueuePolicy::DropOldest`],
+    /// on the thread whose job caused the eviction. The job is dropped without being executed once the function
+    /// returns. If the function panics, the panic will be reported by the panic hook as usual.
+    pub fn on_job_evicted<F: Fn(Job) + Send + Sync + 'static>(self, f: F) -> Self {
+        Self { on_job_evicted: Some(Arc::new(f)), ..self }
+    }
+
+    /// Run every job on a single thread, in the order that the jobs were enqueued. This is a testing aid that makes
+    /// the behaviour of code that uses the pool reproducible, which is useful when debugging concurrency issues or
+    /// flaky tests. It is not intended for production use, since it gives up all parallelism.
+    ///
+    /// Deterministic mode gives the pool exactly one thread, regardless of the thread count options (including
+    /// [`ThreadPoolBuilder::with_env_override`]), and [`ThreadPool::run_or_inline`] always enqueues. It can't be
+    /// combined with options that execute jobs out of order, such as [`Scheduling::Lifo`], a custom [`JobQueue`], or
+    /// a [`FullQueuePolicy`] that runs or drops jobs early: [`ThreadPoolBuilder::finish`] returns
+    /// [`Error::InvalidConfig`] if it is.
+    ///
+    /// Jobs that wait on one another (for example, by joining a handle to another job) may deadlock, as they would on
+    /// any pool with a single thread.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(8)
+    ///     .with_deterministic(true)
+    ///     .finish()
+    ///     .unwrap();
+    /// assert_eq!(pool.thread_count(), 1);
+    ///
+    /// let order = Arc::new(Mutex::new(Vec::new()));
+    /// for i in 0..100 {
+    ///     let order = order.clone();
+    ///     pool.run_or_inline(move || order.lock().unwrap().push(i));
+    /// }
+    /// pool.join_all().unwrap();
+    /// assert!(order.lock().unwrap().iter().copied().eq(0..100));
+    /// ```
+    pub fn with_deterministic(self, deterministic: bool) -> Self {
+        Self { deterministic, ..self }
+    }
+
+    /// Use a custom [`JobQueue`] to hold the jobs of this [`ThreadPool`], created with the given function. This allows
+    /// for queueing disciplines other than those provided by [`ThreadPoolBuilder::with_scheduling`].
+    ///
+    /// This takes a function rather than a queue because a queue can only belong to one pool (closing it when the pool
+    /// shuts down would otherwise close it for every other pool too), while a builder may create many. The function is
+    /// called once by [`ThreadPoolBuilder::finish`], so every pool created from the builder (or a clone of it, such as
+    /// those returned by [`ThreadPool::to_builder`]) has a queue of its own. A custom queue can't be combined with
+    /// [`ThreadPoolBuilder::with_scheduling`] or [`ThreadPoolBuilder::with_queue_capacity`], since it decides for
+    /// itself how jobs are ordered and how many may be queued.
+    ///
+    /// The built-in queues don't implement [`JobQueue`]: workers dispatch to them statically, so that pools without a
+    /// custom queue don't pay for dynamic dispatch on every job.
+    ///
+    /// ```
+    /// use std::{collections::VecDeque, sync::{Condvar, Mutex}, time::Instant};
+    /// use lagoon::{Job, JobQueue};
+    ///
+    /// // A FIFO queue built on a mutex and a condition variable
+    /// #[derive(Default)]
+    /// struct MyQueue {
+    ///     jobs: Mutex<(VecDeque<Job>, bool)>,
+    ///     available: Condvar,
+    /// }
+    ///
+    /// impl JobQueue for MyQueue {
+    ///     fn push(&self, job: Job) {
+    ///         self.jobs.lock().unwrap().0.push_back(job);
+    ///         self.available.notify_one();
+    ///     }
+    ///
+    ///     fn pop(&self, deadline: Option<Instant>) -> Option<Job> {
+    ///         let mut jobs = self.jobs.lock().unwrap();
+    ///         loop {
+    ///             match jobs.0.pop_front() {
+    ///                 Some(job) => break Some(job),
+    ///                 None if jobs.1 => break None,
+    ///                 None => match deadline {
+    ///                     Some(deadline) => {
+    ///                         let timeout = deadline.checked_duration_since(Instant::now())?;
+    ///                         jobs = self.available.wait_timeout(jobs, timeout).unwrap().0;
+    ///                     },
+    ///                     None => jobs = self.available.wait(jobs).unwrap(),
+    ///                 },
+    ///             }
+    ///         }
+    ///     }
+    ///
+    ///     fn try_pop(&self) -> Option<Job> { self.jobs.lock().unwrap().0.pop_front() }
+    ///
+    ///     fn len(&self) -> usize { self.jobs.lock().unwrap().0.len() }
+    ///
+    ///     fn close(&self) {
+    ///         self.jobs.lock().unwrap().1 = true;
+    ///         self.available.notify_all();
+    ///     }
+    ///
+    ///     fn is_closed(&self) -> bool { self.jobs.lock().unwrap().1 }
+    /// }
+    ///
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_queue(MyQueue::default)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// for i in 0..100 {
+    ///     let tx = tx.clone();
+    ///     pool.run(move || tx.send(i).unwrap());
+    /// }
+    /// pool.join_all().unwrap();
+    /// assert_eq!(rx.try_iter().sum::<u32>(), 4950);
+    /// ```
+    pub fn with_queue<Q, F>(self, f: F) -> Self
+    where
+        Q: JobQueue + 'static,
+        F: Fn() -> Q + Send + Sync + 'static,
+    {
+        Self { queue: Some(Arc::new(move || Box::new(f()) as Box<dyn JobQueue>)), ..self }
+    }
+
+    /// Configure what the [`ThreadPool`] does when a job panics. If unspecified, the panic is caught and the worker
+    /// continues running ([`PanicPolicy::Catch`]).
+    ///
+    /// See [`PanicPolicy`] for the available options. The policy applies uniformly to all jobs, including those with
+    /// handles, scoped jobs, and jobs executed on the calling thread by [`ThreadPool::run_or_inline`]. Panics may be
+    /// observed regardless of the policy with [`ThreadPoolBuilder::on_panic`] and [`ThreadPool::panic_count`].
+    ///
+    /// ```
+    /// // Jobs on this pool mutate shared state that can't be trusted after a panic
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_panic_policy(lagoon::PanicPolicy::Abort)
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    pub fn with_panic_policy(self, policy: PanicPolicy) -> Self {
+        Self { panic_policy: policy, ..self }
+    }
+
+    /// Configure what the [`ThreadPool`] does when a job panics using one of the simpler strategies of
+    /// [`PanicStrategy`]. This is shorthand for [`ThreadPoolBuilder::with_panic_policy`] with the corresponding
+    /// [`PanicPolicy`], and applies uniformly to all jobs in the same way.
+    ///
+    /// ```
+    /// use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    /// use lagoon::PanicStrategy;
+    ///
+    /// let handled = Arc::new(AtomicUsize::new(0));
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_panic_strategy(PanicStrategy::CatchAndCount)
+    ///     .on_panic({
+    ///         let handled = handled.clone();
+    ///         move |_| { handled.fetch_add(1, Ordering::Relaxed); }
+    ///     })
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// pool.run(|| panic!("oh no"));
+    /// pool.wait_idle();
+    /// assert_eq!(pool.panic_count(), 1);
+    /// assert_eq!(handled.load(Ordering::Relaxed), 1);
+    /// assert_eq!(pool.config().panic_policy, lagoon::PanicPolicy::Catch);
+    /// ```
+    pub fn with_panic_strategy(self, strategy: PanicStrategy) -> Self {
+        self.with_panic_policy(strategy.into())
+    }
+
+    /// Invoke the given function when a non-fatal problem occurs while configuring the pool or a worker thread, such as
+    /// failing to pin a worker to a core. Problems with a worker are reported on the worker thread in question.
+    ///
+    /// If unspecified, warnings will be printed to stderr.
+    pub fn on_warning<F: Fn(Warning) + Send + Sync + 'static>(self, f: F) -> Self {
+        Self { on_warning: Some(Arc::new(f)), ..self }
+    }
+
+    /// Configure whether the [`ThreadPool`] catches panics that occur in jobs. If unspecified, panics are caught.
+    ///
+    /// Catching panics has a small cost for every job. Disabling it calls each job directly, which may be worthwhile
+    /// for pools with many trivially small jobs that never panic (or for programs built with `panic = "abort"`, where
+    /// panics can't be caught anyway). However, a job that panics will then unwind and kill the worker that ran it:
+    ///
+    /// - The pool is not replenished, so it continues with one fewer worker. Once every worker has died, jobs will no
+    ///   longer be executed.
+    /// - The [`PanicPolicy`] is not applied, [`ThreadPoolBuilder::on_panic`] is not invoked, and the panic is not
+    ///   counted by [`ThreadPool::panic_count`]. Handles to jobs that panicked still receive their panic if the
+    ///   pool uses [`PanicPolicy::Propagate`].
+    /// - The [`ThreadPoolBuilder::before_stop`] hook is not invoked on the worker.
+    /// - [`ThreadPool::join_all`] returns the payload of the panic.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_catch_panics(false)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// for i in 0..1000 {
+    ///     pool.run(move || { std::hint::black_box(i); });
+    /// }
+    /// pool.join_all().unwrap();
+    /// ```
+    pub fn with_catch_panics(self, catch: bool) -> Self {
+        Self { catch_panics: Some(catch), ..self }
+    }
+
+    pub(crate) fn catch_panics(&self) -> bool { self.catch_panics.unwrap_or(true) }
+
+    /// Wrap every job enqueued on the [`ThreadPool`] with the given function, which is given the job and must return
+    /// a function that calls it. This is useful for cross-cutting behaviour like propagating a logging context,
+    /// timing jobs, or holding a guard while a job runs.
+    ///
+    /// The wrapper is invoked on the thread that enqueues the job, at the time that it is enqueued, so it may capture
+    /// context from that thread. The function that it returns is called on the thread that executes the job. Every
+    /// kind of job passes through the wrapper, including scoped jobs and jobs with handles. The wrapper may be
+    /// given several times, in which case the wrappers compose in the order that they were given: the first is the
+    /// outermost.
+    ///
+    /// The job given to the wrapper may borrow from a scope, so it can't be kept beyond the function that the wrapper
+    /// returns. That function must call the job exactly once: a job that is dropped without being called never
+    /// completes, so anything waiting on it (such as a scope) may block forever.
+    ///
+    /// ```
+    /// use std::time::Instant;
+    ///
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .wrap_jobs(|job| {
+    ///         let enqueued = Instant::now();
+    ///         Box::new(move || {
+    ///             let started = Instant::now();
+    ///             job();
+    ///             println!("Waited for {:?}, ran for {:?}", started - enqueued, started.elapsed());
+    ///         })
+    ///     })
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// pool.run(|| println!("Hello, world!"));
+    /// ```
+    pub fn wrap_jobs<F>(self, wrapper: F) -> Self
+    where
+        F: for<'a> Fn(Box<dyn FnOnce() + Send + 'a>) -> Box<dyn FnOnce() + Send + 'a> + Send + Sync + 'static,
+    {
+        let mut job_wrappers = self.job_wrappers;
+        job_wrappers.push(Arc::new(wrapper));
+        Self { job_wrappers, ..self }
+    }
+
+    /// Give each worker thread its own state, created on the worker with the given function when it starts (before
+    /// [`ThreadPoolBuilder::after_start`] is invoked). The function is given the index of the worker, from `0` to the
+    /// number of threads in the pool. The state is dropped when the worker stops.
+    ///
+    /// Jobs may be given mutable access to the state of the worker that executes them with
+    /// [`ThreadPool::run_with_state`]. This is useful for resources that are expensive to create, such as database
+    /// connections or scratch buffers, since each is created once per worker rather than once per job. The state need
+    /// not be [`Send`], since it never leaves its worker.
+    ///
+    /// If the function panics, the panic will be reported by the panic hook as usual and the worker will run without
+    /// state.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(4)
+    ///     .with_worker_state(|idx| format!("buffer for worker {}", idx))
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// for i in 0..10 {
+    ///     pool.run_with_state(move |buf: &mut String| {
+    ///         buf.push_str(&i.to_string());
+    ///         buf.truncate(32);
+    ///     });
+    /// }
+    /// ```
+    pub fn with_worker_state<S: 'static, F: Fn(usize) -> S + Send + Sync + 'static>(self, init: F) -> Self {
+        Self { worker_state: Some(Arc::new(move |idx| Box::new(init(idx)) as Box<dyn std::any::Any>)), ..self }
+    }
+
+    /// Give each worker thread its own random number generator, seeded with the given seed plus the index of the
+    /// worker. Jobs may access the generator of the worker that executes them with [`worker_rng`].
+    ///
+    /// This makes randomness in parallel workloads reproducible without each job seeding its own generator, while
+    /// keeping the sequences of different workers distinct. Note that which jobs a worker executes is generally not
+    /// deterministic. A worker that is recycled (see [`ThreadPoolBuilder::with_thread_recycling`]) has its replacement
+    /// seeded afresh, restarting its sequence.
+    ///
+    /// ```
+    /// use rand::Rng;
+    ///
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(4)
+    ///     .with_rng_seed(42)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// for _ in 0..10 {
+    ///     let tx = tx.clone();
+    ///     pool.run(move || tx.send(lagoon::worker_rng().unwrap().gen_range(0..6)).unwrap());
+    /// }
+    /// drop(tx);
+    /// assert!(rx.iter().all(|roll| roll < 6));
+    /// ```
+    #[cfg(feature = "rand")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "rand")))]
+    pub fn with_rng_seed(self, base: u64) -> Self {
+        Self { rng_seed: Some(base), ..self }
+    }
+
+    /// Invoke the given function when the number of queued jobs reaches the given threshold, giving it the length of
+    /// the queue. This gives early warning that the pool is saturated, without needing to poll
+    /// [`ThreadPool::queue_len`].
+    ///
+    /// The length of the queue is checked on the thread enqueueing a job, immediately after it has been enqueued. To
+    /// avoid a flood of calls while the pool remains saturated, the function is only invoked when the queue grows past
+    /// the threshold, and not again until a job has been enqueued while the queue is below the threshold. If the
+    /// function panics, the panic will be reported by the panic hook as usual but the job will still be enqueued.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Barrier, atomic::{AtomicUsize, Ordering}};
+    ///
+    /// let crossings = Arc::new(AtomicUsize::new(0));
+    /// let crossings2 = crossings.clone();
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(1)
+    ///     .on_queue_high_water(5, move |_len| { crossings2.fetch_add(1, Ordering::Relaxed); })
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// // Block the only worker so that jobs pile up in the queue
+    /// let barrier = Arc::new(Barrier::new(2));
+    /// let worker_barrier = barrier.clone();
+    /// pool.run(move || { worker_barrier.wait(); });
+    /// while pool.queue_len() > 0 { std::thread::yield_now(); }
+    ///
+    /// for _ in 0..10 {
+    ///     pool.run(|| {});
+    /// }
+    /// assert_eq!(crossings.load(Ordering::Relaxed), 1);
+    /// barrier.wait();
+    /// ```
+    pub fn on_queue_high_water<F: Fn(usize) + Send + Sync + 'static>(self, threshold: usize, f: F) -> Self {
+        Self { on_queue_high_water: Some((threshold, Arc::new(f))), ..self }
+    }
+
+    /// Invoke the given function on the thread executing each job, immediately before it is executed.
+    ///
+    /// This is useful for lightweight instrumentation that only needs to observe jobs: see
+    /// [`ThreadPoolBuilder::wrap_jobs`] for changing how they run. If the function panics, the panic will be reported
+    /// by the panic hook as usual but the job will still be executed.
+    pub fn on_job_start<F: Fn(JobInfo) + Send + Sync + 'static>(self, f: F) -> Self {
+        Self { on_job_start: Some(Arc::new(f)), ..self }
+    }
+
+    /// Invoke the given function on the thread executing each job, immediately after it has been executed. The
+    /// function is given the time that the job took to execute and whether it panicked.
+    ///
+    /// If the function panics, the panic will be reported by the panic hook as usual but the worker will continue to
+    /// run. If panics are not caught (see [`ThreadPoolBuilder::with_catch_panics`]), the function is not invoked for
+    /// jobs that panic.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let latencies = Arc::new(Mutex::new(Vec::new()));
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .on_job_end({
+    ///         let latencies = latencies.clone();
+    ///         move |_, duration, outcome| {
+    ///             if outcome == lagoon::JobOutcome::Completed {
+    ///                 latencies.lock().unwrap().push(duration);
+    ///             }
+    ///         }
+    ///     })
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// for _ in 0..10 {
+    ///     pool.run(|| std::thread::sleep(std::time::Duration::from_millis(1)));
+    /// }
+    /// pool.join_all().unwrap();
+    /// assert_eq!(latencies.lock().unwrap().len(), 10);
+    /// ```
+    pub fn on_job_end<F: Fn(JobInfo, Duration, JobOutcome) + Send + Sync + 'static>(self, f: F) -> Self {
+        Self { on_job_end: Some(Arc::new(f)), ..self }
+    }
+
+    /// Invoke the given function with the payload of every panic that occurs in a job, on the thread that executed
+    /// the job. The function is invoked before the panic is handled according to the [`PanicPolicy`] (so before the
+    /// process is aborted, with [`PanicPolicy::Abort`]).
+    ///
+    /// This is useful for logging or collecting metrics about failing jobs. The number of panics may also be queried
+    /// with [`ThreadPool::panic_count`].
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .on_panic(|payload| {
+    ///         if let Some(msg) = payload.downcast_ref::<&str>() {
+    ///             eprintln!("A job panicked: {}", msg);
+    ///         }
+    ///     })
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    pub fn on_panic<F: Fn(&(dyn std::any::Any + Send)) + Send + Sync + 'static>(self, f: F) -> Self {
+        Self { on_panic: Some(Arc::new(f)), ..self }
+    }
+
+    /// Pin each worker thread to a specific CPU core, with worker `i` being pinned to `cores[i % cores.len()]`.
+    ///
+    /// This composes with automatic thread count detection: if the pool has more workers than there are cores in the
+    /// list, pinning wraps around to the start of the list. An empty list results in no pinning.
+    ///
+    /// If a worker cannot be pinned, the pool will still be created and a [`Warning::CoreAffinity`] will be emitted
+    /// (see [`ThreadPoolBuilder::on_warning`]).
+    ///
+    /// ```
+    /// // Use two workers per core, pinned to the first two cores
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(4)
+    ///     .with_core_affinity(vec![0, 1])
+    ///     .on_warning(|w| println!("{}", w))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "affinity")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "affinity")))]
+    pub fn with_core_affinity(self, cores: Vec<usize>) -> Self {
+        Self { core_affinity: Some(cores), ..self }
+    }
+
+    /// Pin each worker thread to a core chosen according to the given [`AffinityStrategy`], based on the CPU topology
+    /// of the system. This can't be combined with [`ThreadPoolBuilder::with_core_affinity`].
+    ///
+    /// The topology is only read on Linux. If it can't be read, workers are not pinned, the pool will still be
+    /// created and a [`Warning::CpuTopology`] will be emitted (see [`ThreadPoolBuilder::on_warning`]). The resulting
+    /// assignment of workers to cores may be inspected with [`ThreadPool::worker_cores`].
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_affinity_strategy(lagoon::AffinityStrategy::Spread)
+    ///     .on_warning(|w| println!("{}", w))
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// println!("Workers were pinned to cores {:?}", pool.worker_cores());
+    /// ```
+    #[cfg(feature = "affinity")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "affinity")))]
+    pub fn with_affinity_strategy(self, strategy: AffinityStrategy) -> Self {
+        Self { affinity_strategy: Some(strategy), ..self }
+    }
+
+    /// Give the threads owned by this [`ThreadPool`] the given scheduling priority. If unspecified, threads will have
+    /// the same priority as those created by [`std::thread::spawn`].
+    ///
+    /// The priority is applied by each worker thread after it is spawned. On Linux, this sets the niceness of the
+    /// thread. On other Unix platforms, it selects a priority from the range of the thread's scheduling policy. On
+    /// Windows, it uses `SetThreadPriority`.
+    ///
+    /// Raising priority often requires elevated privileges. If the priority of a worker cannot be set, the pool will
+    /// still be created and a [`Warning::Priority`] will be emitted (see [`ThreadPoolBuilder::on_warning`]).
+    ///
+    /// On Apple platforms, a QoS class given to `with_qos_class` (with the `qos` feature) takes precedence over this
+    /// option, since setting a priority directly opts the thread out of QoS scheduling.
+    ///
+    /// ```
+    /// // A pool for background work that should never compete with the main thread
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_os_priority(lagoon::Priority::Low)
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "priority")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "priority")))]
+    pub fn with_os_priority(self, priority: Priority) -> Self {
+        Self { os_priority: Some(priority), ..self }
+    }
+
+    /// Run the threads owned by this [`ThreadPool`] under a realtime scheduling policy, such as `SCHED_FIFO`. This is
+    /// useful for latency-critical work like audio processing.
+    ///
+    /// The policy is applied by each worker thread after it is spawned, using `pthread_setschedparam`. This commonly
+    /// fails without elevated privileges (`CAP_SYS_NICE` or an appropriate `RLIMIT_RTPRIO` on Linux). If it fails, the
+    /// worker falls back to running under its normal scheduling policy, the pool will still be created, and a
+    /// [`Warning::RealtimePolicy`] will be emitted (see [`ThreadPoolBuilder::on_warning`]). The outcome for each worker
+    /// may be inspected with [`ThreadPool::realtime_policy_results`].
+    ///
+    /// When this option is set, spawning workers (usually in [`ThreadPoolBuilder::finish`]) waits for every worker to
+    /// apply the policy before returning.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(2)
+    ///     .with_realtime_policy(lagoon::RtPolicy::Fifo { priority: 10 })
+    ///     .on_warning(|_| {})
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// if pool.realtime_policy_results().iter().any(|res| res.is_err()) {
+    ///     println!("Running without realtime scheduling, expect dropouts!");
+    /// }
+    /// ```
+    #[cfg(all(unix, feature = "realtime"))]
+    #[cfg_attr(docsrs, doc(cfg(all(unix, feature = "realtime"))))]
+    pub fn with_realtime_policy(self, policy: RtPolicy) -> Self {
+        Self { realtime_policy: Some(policy), ..self }
+    }
+
+    /// Give the threads owned by this [`ThreadPool`] the given QoS class on Apple platforms (macOS, iOS), using
+    /// `pthread_set_qos_class_self_np`. On other platforms, this option has no effect.
+    ///
+    /// The Apple scheduler heavily favours threads by QoS class: for example, it may restrict threads with a low class
+    /// to efficiency cores. If the class of a worker cannot be set, the pool will still be created and a
+    /// [`Warning::QosClass`] will be emitted (see [`ThreadPoolBuilder::on_warning`]).
+    ///
+    /// If a generic priority is also given with `with_os_priority` (with the `priority` feature), the QoS class takes
+    /// precedence on Apple platforms and the priority is ignored. Elsewhere, the priority is applied as normal.
+    ///
+    /// ```
+    /// // A pool for background indexing, which should yield to everything the user is actually doing
+    /// let indexer = lagoon::ThreadPool::build()
+    ///     .with_thread_name("indexer".to_string())
+    ///     .with_qos_class(lagoon::QosClass::Background)
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "qos")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "qos")))]
+    pub fn with_qos_class(self, class: QosClass) -> Self {
+        Self { qos_class: Some(class), ..self }
+    }
+
+    /// Give the threads owned by this [`ThreadPool`] the given IO priority on Linux, using `ioprio_set`. On other
+    /// platforms, this option has no effect.
+    ///
+    /// This is useful for pools that perform bulk IO (such as scanning a disk) that should not harm the IO latency of
+    /// the rest of the system. If the IO priority of a worker cannot be set, the pool will still be created and a
+    /// [`Warning::IoPriority`] will be emitted (see [`ThreadPoolBuilder::on_warning`]). The `Debug` output of the pool
+    /// includes the number of workers that successfully applied the IO priority.
+    ///
+    /// ```
+    /// let scanner = lagoon::ThreadPool::build()
+    ///     .with_io_priority(lagoon::IoPriority::Idle)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// println!("{:?}", scanner);
+    /// ```
+    #[cfg(feature = "ioprio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ioprio")))]
+    pub fn with_io_priority(self, priority: IoPriority) -> Self {
+        Self { io_priority: Some(priority), ..self }
+    }
+
+    /// Restrict the threads owned by this [`ThreadPool`] to the CPUs of the given NUMA node on Linux, keeping them
+    /// close to memory allocated on that node. This replaces any previous call to
+    /// [`ThreadPoolBuilder::with_numa_interleave`], and can't be combined with
+    /// [`ThreadPoolBuilder::with_core_affinity`].
+    ///
+    /// The topology of the system is read from sysfs. If the node doesn't exist or has no CPUs (or the system is not
+    /// Linux), the pool will still be created without placing its workers and a [`Warning::NumaTopology`] will be
+    /// emitted (see [`ThreadPoolBuilder::on_warning`]). The node that each worker was placed on may be inspected with
+    /// [`ThreadPool::worker_numa_nodes`].
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_numa_node(0)
+    ///     .on_warning(|w| println!("{}", w))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "numa")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "numa")))]
+    pub fn with_numa_node(self, node: usize) -> Self {
+        Self { numa_placement: Some(os::NumaPlacement::Node(node)), ..self }
+    }
+
+    /// Distribute the threads owned by this [`ThreadPool`] evenly across the NUMA nodes of the system on Linux, with
+    /// worker `i` being restricted to the CPUs of the `i % n`th of the `n` nodes that have CPUs. This replaces any
+    /// previous call to [`ThreadPoolBuilder::with_numa_node`], and can't be combined with
+    /// [`ThreadPoolBuilder::with_core_affinity`].
+    ///
+    /// Failures are handled as with [`ThreadPoolBuilder::with_numa_node`]. A system without NUMA has a single node, so
+    /// placement has no effect beyond restricting workers to the CPUs of that node.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_numa_interleave(true)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// println!("Workers were placed on nodes {:?}", pool.worker_numa_nodes());
+    /// ```
+    #[cfg(feature = "numa")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "numa")))]
+    pub fn with_numa_interleave(self, interleave: bool) -> Self {
+        Self { numa_placement: if interleave { Some(os::NumaPlacement::Interleave) } else { None }, ..self }
+    }
+
+    // Whether workers must warm up before spawning them is complete
+    pub(crate) fn warms_up(&self) -> bool { self.warmup.is_some() || self.prefault_stack.is_some() }
+
+    pub(crate) fn warn(&self, warning: Warning) {
+        match &self.on_warning {
+            Some(f) => f(warning),
+            None => match &self.pool_name {
+                Some(name) => eprintln!("lagoon ({}): {}", name, warning),
+                None => eprintln!("lagoon: {}", warning),
+            },
+        }
+    }
+
+    // Determine the number of threads that the pool should have from the sizing options
+    fn resolve_thread_count(&self) -> Result<usize, Error> {
+        if self.deterministic {
+            return Ok(1);
+        }
+        let detected = || {
+            if self.physical_cores_only { available_physical_concurrency() } else { available_concurrency() }
+                .unwrap_or(ThreadPool::DEFAULT_THREAD_COUNT)
+        };
+        let thread_count = match (self.thread_count, self.reserved_cores, self.thread_count_fraction) {
+            (None, None, None) => match self.preset {
+                Some(preset) => preset.thread_count(detected()),
+                None => detected(),
+            },
+            (Some(thread_count), None, None) => thread_count,
+            (None, Some(reserved), None) => detected().saturating_sub(reserved).max(1),
+            (None, None, Some(fraction)) => {
+                if !fraction.is_finite() || fraction <= 0.0 {
+                    return Err(Error::InvalidConfig("thread count fraction must be finite and positive"));
+                }
+                ((detected() as f32 * fraction).round() as usize).max(1)
+            },
+            _ => return Err(Error::InvalidConfig(
+                "only one of a thread count, reserved cores, or thread count fraction may be given",
+            )),
+        };
+
+        // A valid value in the environment takes precedence over everything else
+        let thread_count = match self.env_override.as_ref().and_then(std::env::var_os) {
+            Some(value) => match value.to_str().and_then(|s| s.trim().parse::<usize>().ok()) {
+                Some(n) if n > 0 => n,
+                _ => {
+                    self.warn(Warning::InvalidEnvOverride {
+                        var: self.env_override.clone().unwrap_or_default(),
+                        value: value.to_string_lossy().into_owned(),
+                    });
+                    thread_count
+                },
+            },
+            None => thread_count,
+        };
+
+        match system_thread_limit() {
+            _ if thread_count == 0 => Err(Error::NoThreads),
+            limit if thread_count > limit => Err(Error::TooManyThreads { requested: thread_count, limit }),
+            _ => Ok(thread_count),
+        }
+    }
+
+    // Check the options that aren't related to the thread count
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(name) = self.thread_name.as_ref().filter(|name| name.is_empty() || name.contains('\0')) {
+            return Err(Error::InvalidThreadName(name.clone()));
+        }
+        if self.pool_name.as_ref().is_some_and(|name| name.contains('\0')) {
+            return Err(Error::InvalidConfig("pool name may not contain NUL bytes"));
+        }
+        #[cfg(feature = "affinity")]
+        if self.core_affinity.is_some() && self.affinity_strategy.is_some() {
+            return Err(Error::InvalidConfig("core affinity can't be combined with an affinity strategy"));
+        }
+        #[cfg(all(feature = "affinity", feature = "numa"))]
+        if (self.core_affinity.is_some() || self.affinity_strategy.is_some()) && self.numa_placement.is_some() {
+            return Err(Error::InvalidConfig("core affinity can't be combined with NUMA placement"));
+        }
+        if let Some(size) = self.thread_stack_size.filter(|size| *size < Self::MIN_STACK_SIZE) {
+            return Err(Error::StackSizeTooSmall { size, min: Self::MIN_STACK_SIZE });
+        }
+        if let (Some(bytes), Some(size)) = (self.prefault_stack, self.thread_stack_size) {
+            if bytes.saturating_add(Self::MIN_STACK_SIZE) > size {
+                return Err(Error::InvalidConfig("prefaulted stack must leave room for the rest of the stack"));
+            }
+        }
+        if self.queue_capacity == Some(0) {
+            return Err(Error::InvalidConfig("queue capacity must be non-zero"));
+        }
+        if self.deterministic
+            && (self.scheduling != Scheduling::Fifo
+                || self.queue.is_some()
+                || self.inline_overflow.is_some()
+                || !matches!(self.full_queue_policy, FullQueuePolicy::Block | FullQueuePolicy::Fail))
+        {
+            return Err(Error::InvalidConfig("deterministic mode can't be combined with options that reorder jobs"));
+        }
+        if self.queue.is_some()
+            && (self.queue_capacity.is_some()
+                || self.scheduling != Scheduling::default()
+                || self.full_queue_policy != FullQueuePolicy::default())
+        {
+            return Err(Error::InvalidConfig("a custom job queue has its own scheduling and capacity"));
+        }
+        Ok(())
+    }
+
+    /// Finish configuration, returning a [`ThreadPool`].
+    ///
+    /// The configuration is checked before any threads are spawned, and an error is returned if it is invalid. If the
+    /// pool has more threads than the system allows, [`Error::TooManyThreads`] is returned. The limit is detected on
+    /// Linux; elsewhere (or if detection fails), pools are limited to 65536 threads. If spawning a thread fails, any
+    /// threads that were already spawned are stopped and joined before [`Error::Spawn`] is returned.
+    ///
+    /// ```
+    /// use lagoon::{ThreadPool, Error};
+    ///
+    /// assert!(matches!(
+    ///     ThreadPool::build().with_thread_count(usize::MAX).finish(),
+    ///     Err(Error::TooManyThreads { .. }),
+    /// ));
+    /// assert!(matches!(ThreadPool::build().with_thread_count(0).finish(), Err(Error::NoThreads)));
+    /// assert!(matches!(
+    ///     ThreadPool::build().with_thread_name(String::new()).finish(),
+    ///     Err(Error::InvalidThreadName(_)),
+    /// ));
+    /// assert!(matches!(
+    ///     ThreadPool::build().with_thread_name("work\0er".to_string()).finish(),
+    ///     Err(Error::InvalidThreadName(_)),
+    /// ));
+    /// assert!(matches!(
+    ///     ThreadPool::build().with_thread_stack_size(1).finish(),
+    ///     Err(Error::StackSizeTooSmall { size: 1, .. }),
+    /// ));
+    /// assert!(matches!(ThreadPool::build().with_queue_capacity(0).finish(), Err(Error::InvalidConfig(_))));
+    /// ```
+    ///
+    /// A failure to spawn leaves no threads behind:
+    ///
+    /// ```
+    /// use std::{io, sync::{Arc, atomic::{AtomicUsize, Ordering}}};
+    ///
+    /// let running = Arc::new(AtomicUsize::new(0));
+    /// let running2 = running.clone();
+    /// let res = lagoon::ThreadPool::build()
+    ///     .with_thread_count(4)
+    ///     .with_spawner(move |config, f| {
+    ///         if config.index == 3 {
+    ///             return Err(io::Error::new(io::ErrorKind::Other, "no more threads"));
+    ///         }
+    ///         let running = running2.clone();
+    ///         running.fetch_add(1, Ordering::SeqCst);
+    ///         config.builder().spawn(move || {
+    ///             f();
+    ///             running.fetch_sub(1, Ordering::SeqCst);
+    ///         })
+    ///     })
+    ///     .finish();
+    ///
+    /// assert!(matches!(res, Err(lagoon::Error::Spawn { spawned: 3, .. })));
+    /// assert_eq!(running.load(Ordering::SeqCst), 0);
+    /// ```
+    pub fn finish(self) -> Result<ThreadPool, Error> {
+        self.validate()?;
+        let thread_count = self.resolve_thread_count()?;
+
+        let (tx, rx) = match &self.queue {
+            Some(queue) => queue::custom(queue()),
+            None => queue::new(self.scheduling, self.queue_capacity),
+        };
+        #[cfg(feature = "numa")]
+        let numa_nodes = match self.numa_placement.map(os::numa_nodes) {
+            Some(Ok(nodes)) => nodes,
+            Some(Err(error)) => {
+                self.warn(Warning::NumaTopology { error });
+                Vec::new()
+            },
+            None => Vec::new(),
+        };
+        #[cfg(feature = "affinity")]
+        let core_affinity = match (&self.core_affinity, self.affinity_strategy) {
+            (Some(cores), _) => cores.clone(),
+            (None, Some(strategy)) => match os::cpu_topology() {
+                Ok(cpus) => strategy.assign(cpus),
+                Err(error) => {
+                    self.warn(Warning::CpuTopology { error });
+                    Vec::new()
+                },
+            },
+            (None, None) => Vec::new(),
+        };
+        let shared = Arc::new(Shared {
+            resolved: PoolConfig {
+                thread_count,
+                pool_name: self.pool_name.clone(),
+                thread_name: self.thread_name.clone(),
+                thread_stack_size: self.thread_stack_size,
+                scheduling: self.scheduling,
+                idle_strategy: self.idle_strategy,
+                queue_capacity: self.queue_capacity,
+                panic_policy: self.panic_policy,
+                spawn_mode: self.spawn_mode,
+                deterministic: self.deterministic,
+            },
+            config: self,
+            thread_count,
+            rx,
+            handles: Mutex::new(Vec::new()),
+            spawn_lock: Mutex::new(()),
+            spawned: AtomicUsize::new(0),
+            threads_recycled: AtomicUsize::new(0),
+            idle: AtomicUsize::new(0),
+            outstanding: AtomicUsize::new(0),
+            active: AtomicUsize::new(0),
+            quiescent_lock: Mutex::new(()),
+            quiescent: std::sync::Condvar::new(),
+            panicked: AtomicUsize::new(0),
+            next_job_id: AtomicUsize::new(0),
+            above_high_water: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            pause: Arc::new(context::PauseState::new()),
+            warmed_up: Mutex::new(vec![false; thread_count]),
+            warmed_up_reported: std::sync::Condvar::new(),
+            #[cfg(all(unix, feature = "realtime"))]
+            realtime_results: Mutex::new((0..thread_count).map(|_| None).collect()),
+            #[cfg(all(unix, feature = "realtime"))]
+            realtime_reported: std::sync::Condvar::new(),
+            #[cfg(feature = "ioprio")]
+            io_priority_applied: AtomicUsize::new(0),
+            #[cfg(feature = "numa")]
+            numa_nodes,
+            #[cfg(feature = "numa")]
+            numa_placed: Mutex::new(vec![None; thread_count]),
+            #[cfg(feature = "affinity")]
+            core_affinity,
+            #[cfg(feature = "metrics")]
+            jobs_executed: (0..thread_count).map(|_| AtomicUsize::new(0)).collect(),
+            #[cfg(feature = "scope")]
+            temporaries: worker::Temporaries::default(),
+        });
+
+        if shared.config.spawn_mode == SpawnMode::Eager {
+            if let Err(err) = worker::spawn_workers(&shared, thread_count) {
+                // Don't leak the workers that were spawned before the failure: disconnecting the queue tells them to
+                // stop, so wait for them to do so.
+                drop(tx);
+                let handles = std::mem::take(&mut *shared.handles.lock().unwrap());
+                for handle in handles {
+                    let _ = handle.join();
+                }
+                return Err(err);
+            }
+        }
+
+        Ok(ThreadPool { tx: Arc::new(tx), shared })
+    }
 }
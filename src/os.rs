@@ -0,0 +1,379 @@
+use std::io;
+
+/// A cross-platform scheduling priority for worker threads, applied with [`ThreadPoolBuilder::with_os_priority`].
+///
+/// [`ThreadPoolBuilder::with_os_priority`]: crate::ThreadPoolBuilder::with_os_priority
+#[cfg(feature = "priority")]
+#[cfg_attr(docsrs, doc(cfg(feature = "priority")))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// A priority lower than that of normal threads. Useful for background work that should never compete with
+    /// latency-sensitive threads.
+    Low,
+    /// The priority that threads normally have.
+    Normal,
+    /// A priority higher than that of normal threads. This usually requires elevated privileges.
+    High,
+}
+
+// Set the scheduling priority of the current thread. On Linux, this sets the niceness of the thread.
+#[cfg(all(feature = "priority", target_os = "linux"))]
+pub(crate) fn set_priority(priority: Priority) -> io::Result<()> {
+    let nice = match priority {
+        Priority::Low => 10,
+        Priority::Normal => 0,
+        Priority::High => -10,
+    };
+    // Safety: `gettid` has no preconditions and `setpriority` is given the ID of the current thread
+    let res = unsafe {
+        let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+        libc::setpriority(libc::PRIO_PROCESS as _, tid, nice)
+    };
+    if res == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+// Set the scheduling priority of the current thread. On non-Linux Unix platforms, `setpriority` applies to the whole
+// process so we use the priority range of the current scheduling policy instead.
+#[cfg(all(feature = "priority", unix, not(target_os = "linux")))]
+pub(crate) fn set_priority(priority: Priority) -> io::Result<()> {
+    // Safety: all pointers passed refer to valid stack locations and the thread is the current thread
+    unsafe {
+        let thread = libc::pthread_self();
+        let mut policy = 0;
+        let mut param = std::mem::zeroed::<libc::sched_param>();
+        let res = libc::pthread_getschedparam(thread, &mut policy, &mut param);
+        if res != 0 {
+            return Err(io::Error::from_raw_os_error(res));
+        }
+
+        let (min, max) = (libc::sched_get_priority_min(policy), libc::sched_get_priority_max(policy));
+        param.sched_priority = match priority {
+            Priority::Low => min,
+            Priority::Normal => min + (max - min) / 2,
+            Priority::High => max,
+        };
+        match libc::pthread_setschedparam(thread, policy, &param) {
+            0 => Ok(()),
+            res => Err(io::Error::from_raw_os_error(res)),
+        }
+    }
+}
+
+// Set the scheduling priority of the current thread.
+#[cfg(all(feature = "priority", windows))]
+pub(crate) fn set_priority(priority: Priority) -> io::Result<()> {
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentThread,
+        SetThreadPriority,
+        THREAD_PRIORITY_HIGHEST,
+        THREAD_PRIORITY_LOWEST,
+        THREAD_PRIORITY_NORMAL,
+    };
+
+    let priority = match priority {
+        Priority::Low => THREAD_PRIORITY_LOWEST,
+        Priority::Normal => THREAD_PRIORITY_NORMAL,
+        Priority::High => THREAD_PRIORITY_HIGHEST,
+    };
+    // Safety: `GetCurrentThread` returns a pseudo-handle that is always valid for the current thread
+    if unsafe { SetThreadPriority(GetCurrentThread(), priority) } != 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// Set the scheduling priority of the current thread.
+#[cfg(all(feature = "priority", not(any(unix, windows))))]
+pub(crate) fn set_priority(_priority: Priority) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "thread priorities are not supported on this platform"))
+}
+
+/// A realtime scheduling policy for worker threads, applied with [`ThreadPoolBuilder::with_realtime_policy`].
+///
+/// [`ThreadPoolBuilder::with_realtime_policy`]: crate::ThreadPoolBuilder::with_realtime_policy
+#[cfg(all(unix, feature = "realtime"))]
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "realtime"))))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RtPolicy {
+    /// `SCHED_FIFO`: threads run until they block or yield, or are preempted by a higher-priority thread.
+    Fifo {
+        /// The static scheduling priority. On Linux, this must be between 1 and 99.
+        priority: u8,
+    },
+    /// `SCHED_RR`: like [`RtPolicy::Fifo`], but threads of equal priority are time-sliced.
+    RoundRobin {
+        /// The static scheduling priority. On Linux, this must be between 1 and 99.
+        priority: u8,
+    },
+}
+
+// Set the realtime scheduling policy of the current thread
+#[cfg(all(unix, feature = "realtime"))]
+pub(crate) fn set_realtime_policy(policy: RtPolicy) -> io::Result<()> {
+    let (policy, priority) = match policy {
+        RtPolicy::Fifo { priority } => (libc::SCHED_FIFO, priority),
+        RtPolicy::RoundRobin { priority } => (libc::SCHED_RR, priority),
+    };
+    // Safety: `param` is a valid stack location and the thread is the current thread
+    unsafe {
+        let mut param = std::mem::zeroed::<libc::sched_param>();
+        param.sched_priority = priority as libc::c_int;
+        match libc::pthread_setschedparam(libc::pthread_self(), policy, &param) {
+            0 => Ok(()),
+            res => Err(io::Error::from_raw_os_error(res)),
+        }
+    }
+}
+
+// `io::Error` isn't `Clone`, but the errors we produce here are all OS errors and so can be reconstructed accurately
+#[cfg(all(unix, feature = "realtime"))]
+pub(crate) fn copy_error(error: &io::Error) -> io::Error {
+    match error.raw_os_error() {
+        Some(code) => io::Error::from_raw_os_error(code),
+        None => io::Error::new(error.kind(), error.to_string()),
+    }
+}
+
+/// A QoS (quality of service) class for worker threads on Apple platforms, applied with
+/// [`ThreadPoolBuilder::with_qos_class`].
+///
+/// [`ThreadPoolBuilder::with_qos_class`]: crate::ThreadPoolBuilder::with_qos_class
+#[cfg(feature = "qos")]
+#[cfg_attr(docsrs, doc(cfg(feature = "qos")))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QosClass {
+    /// Work that is interacting with the user, such as updating the UI.
+    UserInteractive,
+    /// Work that the user has initiated and is waiting on.
+    UserInitiated,
+    /// The default QoS class.
+    Default,
+    /// Long-running work that the user is aware of but not actively waiting on, such as downloads.
+    Utility,
+    /// Work that the user is not aware of, such as indexing or backups.
+    Background,
+}
+
+// Set the QoS class of the current thread
+#[cfg(all(feature = "qos", any(target_os = "macos", target_os = "ios")))]
+pub(crate) fn set_qos_class(class: QosClass) -> io::Result<()> {
+    use libc::qos_class_t::*;
+
+    let class = match class {
+        QosClass::UserInteractive => QOS_CLASS_USER_INTERACTIVE,
+        QosClass::UserInitiated => QOS_CLASS_USER_INITIATED,
+        QosClass::Default => QOS_CLASS_DEFAULT,
+        QosClass::Utility => QOS_CLASS_UTILITY,
+        QosClass::Background => QOS_CLASS_BACKGROUND,
+    };
+    // Safety: this function has no preconditions and only affects the current thread
+    match unsafe { libc::pthread_set_qos_class_self_np(class, 0) } {
+        0 => Ok(()),
+        res => Err(io::Error::from_raw_os_error(res)),
+    }
+}
+
+// QoS classes only exist on Apple platforms, so this is a no-op elsewhere
+#[cfg(all(feature = "qos", not(any(target_os = "macos", target_os = "ios"))))]
+pub(crate) fn set_qos_class(_class: QosClass) -> io::Result<()> { Ok(()) }
+
+/// An IO scheduling priority for worker threads on Linux, applied with [`ThreadPoolBuilder::with_io_priority`].
+///
+/// [`ThreadPoolBuilder::with_io_priority`]: crate::ThreadPoolBuilder::with_io_priority
+#[cfg(feature = "ioprio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ioprio")))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IoPriority {
+    /// The idle IO class: threads only perform IO when no other thread has asked for disk time.
+    Idle,
+    /// The best-effort IO class, with a level from `0` (highest) to `7` (lowest). Normal threads have level `4`.
+    BestEffort(u8),
+}
+
+// Set the IO priority of the current thread
+#[cfg(all(feature = "ioprio", target_os = "linux"))]
+pub(crate) fn set_io_priority(priority: IoPriority) -> io::Result<()> {
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+    let ioprio = match priority {
+        IoPriority::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        IoPriority::BestEffort(level) => (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | level.min(7) as libc::c_int,
+    };
+    // Safety: `gettid` has no preconditions and `ioprio_set` is given the ID of the current thread
+    let res = unsafe {
+        let tid = libc::syscall(libc::SYS_gettid);
+        libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, ioprio)
+    };
+    if res == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+// IO priorities are only supported on Linux, so this is a no-op elsewhere
+#[cfg(all(feature = "ioprio", not(target_os = "linux")))]
+pub(crate) fn set_io_priority(_priority: IoPriority) -> io::Result<()> { Ok(()) }
+
+// Parse a list of CPUs in the format used by sysfs (i.e: `0-3,8,10-11`)
+#[cfg(all(any(feature = "numa", feature = "affinity"), target_os = "linux"))]
+fn parse_cpu_list(list: &str) -> io::Result<Vec<usize>> {
+    let parse = || {
+        let mut cpus = Vec::new();
+        for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+            match range.split_once('-') {
+                Some((start, end)) => cpus.extend(start.parse::<usize>().ok()?..=end.parse().ok()?),
+                None => cpus.push(range.parse().ok()?),
+            }
+        }
+        Some(cpus)
+    };
+    parse().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("malformed CPU list {:?}", list)))
+}
+
+/// How worker threads should be placed on the CPUs of the system, applied with
+/// [`ThreadPoolBuilder::with_affinity_strategy`].
+///
+/// [`ThreadPoolBuilder::with_affinity_strategy`]: crate::ThreadPoolBuilder::with_affinity_strategy
+#[cfg(feature = "affinity")]
+#[cfg_attr(docsrs, doc(cfg(feature = "affinity")))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AffinityStrategy {
+    /// Place workers on separate physical cores, alternating between sockets, before placing any on sibling
+    /// hyperthreads. This maximises the memory bandwidth and execution resources available to each worker, which
+    /// suits bandwidth-bound work.
+    Spread,
+    /// Place workers on adjacent CPUs, filling the hyperthreads of each core and the cores of each socket before moving
+    /// on to the next. This keeps workers close to the caches that they share, which suits workloads that share data.
+    Compact,
+}
+
+// A CPU of the system, with the socket and physical core that it belongs to
+#[cfg(feature = "affinity")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Cpu {
+    package: isize,
+    core: isize,
+    id: usize,
+}
+
+#[cfg(feature = "affinity")]
+impl AffinityStrategy {
+    // Order the given CPUs in which workers should be placed on them
+    pub(crate) fn assign(self, mut cpus: Vec<Cpu>) -> Vec<usize> {
+        cpus.sort();
+        match self {
+            Self::Compact => cpus.into_iter().map(|cpu| cpu.id).collect(),
+            Self::Spread => {
+                // Rank each CPU by which hyperthread of its core it is and which core of its socket it belongs to, so
+                // that the first hyperthread of every core comes first, interleaving sockets
+                let mut ranked = Vec::with_capacity(cpus.len());
+                let (mut thread, mut core) = (0, 0);
+                for (i, cpu) in cpus.iter().enumerate() {
+                    match cpus[..i].last() {
+                        Some(prev) if (prev.package, prev.core) == (cpu.package, cpu.core) => thread += 1,
+                        Some(prev) if prev.package == cpu.package => (thread, core) = (0, core + 1),
+                        _ => (thread, core) = (0, 0),
+                    }
+                    ranked.push(((thread, core, cpu.package), cpu.id));
+                }
+                ranked.sort();
+                ranked.into_iter().map(|(_, id)| id).collect()
+            },
+        }
+    }
+}
+
+// Read the topology of the online CPUs of the system from sysfs
+#[cfg(all(feature = "affinity", target_os = "linux"))]
+pub(crate) fn cpu_topology() -> io::Result<Vec<Cpu>> {
+    let online = parse_cpu_list(&std::fs::read_to_string("/sys/devices/system/cpu/online")?)?;
+    online
+        .into_iter()
+        .map(|id| {
+            let read = |name| {
+                let path = format!("/sys/devices/system/cpu/cpu{}/topology/{}", id, name);
+                let value = std::fs::read_to_string(&path)?;
+                value.trim().parse::<isize>().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("malformed topology file {:?}", path))
+                })
+            };
+            Ok(Cpu { package: read("physical_package_id")?, core: read("core_id")?, id })
+        })
+        .collect()
+}
+
+// The CPU topology is only read on Linux, so affinity strategies are unsupported elsewhere
+#[cfg(all(feature = "affinity", not(target_os = "linux")))]
+pub(crate) fn cpu_topology() -> io::Result<Vec<Cpu>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "reading the CPU topology is only supported on Linux"))
+}
+
+// Where the workers of a pool should be placed, configured with `ThreadPoolBuilder::with_numa_node` or
+// `ThreadPoolBuilder::with_numa_interleave`
+#[cfg(feature = "numa")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum NumaPlacement {
+    Node(usize),
+    Interleave,
+}
+
+// Find the NUMA nodes that workers should be placed on, along with the CPUs of each, by reading the system topology
+// from sysfs. Nodes without CPUs (i.e: memory-only nodes) can't have workers placed on them, so they are skipped.
+#[cfg(all(feature = "numa", target_os = "linux"))]
+pub(crate) fn numa_nodes(placement: NumaPlacement) -> io::Result<Vec<(usize, Vec<usize>)>> {
+    let mut nodes = Vec::new();
+    for entry in std::fs::read_dir("/sys/devices/system/node")? {
+        let entry = entry?;
+        let node = match entry.file_name().to_str().and_then(|name| name.strip_prefix("node")?.parse().ok()) {
+            Some(node) => node,
+            None => continue,
+        };
+        let list = std::fs::read_to_string(entry.path().join("cpulist"))?;
+        let cpus = parse_cpu_list(&list)?;
+        if !cpus.is_empty() {
+            nodes.push((node, cpus));
+        }
+    }
+    nodes.sort();
+
+    match placement {
+        NumaPlacement::Interleave if nodes.is_empty() => {
+            Err(io::Error::new(io::ErrorKind::NotFound, "no NUMA nodes with CPUs were found"))
+        },
+        NumaPlacement::Interleave => Ok(nodes),
+        NumaPlacement::Node(node) => match nodes.into_iter().find(|(n, _)| *n == node) {
+            Some(node) => Ok(vec![node]),
+            None => {
+                let msg = format!("NUMA node {} does not exist or has no CPUs", node);
+                Err(io::Error::new(io::ErrorKind::NotFound, msg))
+            },
+        },
+    }
+}
+
+// The NUMA topology is only read on Linux, so placement is unsupported elsewhere
+#[cfg(all(feature = "numa", not(target_os = "linux")))]
+pub(crate) fn numa_nodes(_placement: NumaPlacement) -> io::Result<Vec<(usize, Vec<usize>)>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "NUMA placement is only supported on Linux"))
+}
+
+// Restrict the current thread to running on the given CPUs
+#[cfg(all(feature = "numa", target_os = "linux"))]
+pub(crate) fn set_cpu_affinity(cpus: &[usize]) -> io::Result<()> {
+    // Safety: an all-zero `cpu_set_t` is an empty set, CPUs beyond the size of the set are skipped, and
+    // `sched_setaffinity` is given the size of the set that it reads
+    let res = unsafe {
+        let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+        for &cpu in cpus.iter().filter(|cpu| **cpu < libc::CPU_SETSIZE as usize) {
+            libc::CPU_SET(cpu, &mut set);
+        }
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if res == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+#[cfg(all(feature = "numa", not(target_os = "linux")))]
+pub(crate) fn set_cpu_affinity(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "NUMA placement is only supported on Linux"))
+}
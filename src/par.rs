@@ -0,0 +1,127 @@
+use super::*;
+
+/// An iterator that applies a function to the items of another iterator in parallel, yielding the results in the order
+/// that they finish. It may be created with [`ThreadPool::par_map`].
+///
+/// Items are pulled from the underlying iterator lazily: a job is only enqueued for an item when there is room for it,
+/// keeping at most as many jobs in flight as the pool has threads. This bounds the memory used by pending items and
+/// results while keeping every worker busy.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let mut squares = pool.par_map(0..100u64, |x| x * x).collect::<Vec<_>>();
+/// squares.sort();
+///
+/// assert!((0..100u64).map(|x| x * x).eq(squares.into_iter()));
+/// ```
+pub struct ParMap<'pool, I, F, R> {
+    pool: &'pool ThreadPool,
+    iter: I,
+    f: Arc<F>,
+    in_flight: usize,
+    tx: Sender<Option<R>>,
+    rx: Receiver<Option<R>>,
+}
+
+// Sends the result of a job to the iterator. If dropped without sending (i.e: because the job panicked), the iterator
+// is told so that it doesn't wait forever.
+struct ResultSender<R>(Option<Sender<Option<R>>>);
+
+impl<R> ResultSender<R> {
+    fn send(mut self, x: R) {
+        if let Some(tx) = self.0.take() {
+            let _ = tx.send(Some(x));
+        }
+    }
+}
+
+impl<R> Drop for ResultSender<R> {
+    fn drop(&mut self) {
+        if let Some(tx) = self.0.take() {
+            let _ = tx.send(None);
+        }
+    }
+}
+
+// Fold contiguous chunks of the items in parallel, one chunk per thread, then combine the results of the chunks in
+// order
+pub(crate) fn reduce<I, T, F, G>(pool: &ThreadPool, items: I, identity: T, map: F, combine: G) -> T
+where
+    I: IntoIterator,
+    I::Item: Send + 'static,
+    T: Clone + Send + 'static,
+    F: Fn(I::Item) -> T + Send + Sync + 'static,
+    G: Fn(T, T) -> T + Send + Sync + 'static,
+{
+    let mut items = items.into_iter().collect::<Vec<_>>();
+    let (map, combine) = (Arc::new(map), Arc::new(combine));
+    let (len, chunks) = (items.len(), pool.thread_count().min(items.len()));
+    let (tx, rx) = unbounded();
+    // Chunks are split off the end, so go backwards to keep each chunk's index
+    for idx in (0..chunks).rev() {
+        let chunk = items.split_off(len * idx / chunks);
+        let (identity, map, combine) = (identity.clone(), map.clone(), combine.clone());
+        let tx = ResultSender(Some(tx.clone()));
+        pool.run(move || {
+            let acc = chunk.into_iter().fold(identity, |acc, x| combine(acc, map(x)));
+            tx.send((idx, acc));
+        });
+    }
+    drop(tx);
+
+    let mut results = (0..chunks).map(|_| None).collect::<Vec<_>>();
+    for res in rx {
+        match res {
+            Some((idx, x)) => results[idx] = Some(x),
+            None => panic!("a job spawned by reduce panicked"),
+        }
+    }
+    results.into_iter().flatten().fold(identity, |acc, x| combine(acc, x))
+}
+
+impl<'pool, I, F, R> ParMap<'pool, I, F, R> {
+    pub(crate) fn new(pool: &'pool ThreadPool, iter: I, f: F) -> Self {
+        let (tx, rx) = unbounded();
+        Self { pool, iter, f: Arc::new(f), in_flight: 0, tx, rx }
+    }
+}
+
+impl<'pool, I, F, R> Iterator for ParMap<'pool, I, F, R>
+where
+    I: Iterator,
+    I::Item: Send + 'static,
+    F: Fn(I::Item) -> R + Send + Sync + 'static,
+    R: Send + 'static,
+{
+    type Item = R;
+
+    fn next(&mut self) -> Option<R> {
+        // Top up the jobs in flight
+        while self.in_flight < self.pool.thread_count() {
+            let item = match self.iter.next() {
+                Some(item) => item,
+                None => break,
+            };
+            let f = self.f.clone();
+            let tx = ResultSender(Some(self.tx.clone()));
+            self.pool.run(move || tx.send(f(item)));
+            self.in_flight += 1;
+        }
+
+        if self.in_flight == 0 {
+            return None;
+        }
+        self.in_flight -= 1;
+        // We hold a sender, so the channel can't disconnect
+        match self.rx.recv().expect("par_map channel disconnected") {
+            Some(x) => Some(x),
+            None => panic!("a job spawned by par_map panicked"),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        (lower.saturating_add(self.in_flight), upper.and_then(|upper| upper.checked_add(self.in_flight)))
+    }
+}
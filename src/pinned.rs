@@ -0,0 +1,70 @@
+use super::*;
+
+use std::panic::{self, AssertUnwindSafe};
+
+type PinnedJob<S> = Box<dyn FnOnce(&mut S) + Send>;
+
+/// A handle to a worker that has been dedicated to running a sequence of jobs on the same thread, sharing state that
+/// need not be [`Send`]. It may be created with [`ThreadPool::run_pinned`].
+///
+/// The state is created on the dedicated worker and never leaves it, so it may contain types like [`std::rc::Rc`] or
+/// handles to single-threaded resources. The jobs themselves must still be [`Send`], since they are sent to the worker
+/// from other threads. Jobs are executed in the order that they are enqueued.
+///
+/// The worker remains dedicated to the handle until the handle is dropped, after which it finishes any outstanding
+/// jobs, drops the state, and returns to executing other jobs from the pool. Note that:
+///
+/// - A dedicated worker is unavailable to the rest of the pool, so a pool with a single thread can't execute other jobs
+///   while a handle exists (and waiting on such jobs will deadlock).
+/// - [`ThreadPool::join_all`] and [`ThreadPool::wait_idle`] wait for every handle to be dropped.
+/// - Jobs enqueued before a worker has picked up the handle are held until one does.
+///
+/// ```
+/// use std::rc::Rc;
+///
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let pinned = pool.run_pinned(|| Rc::new(std::cell::Cell::new(0)));
+/// for i in 0..10 {
+///     pinned.run(move |counter| counter.set(counter.get() + i));
+/// }
+///
+/// let (tx, rx) = std::sync::mpsc::channel();
+/// pinned.run(move |counter| tx.send(counter.get()).unwrap());
+/// assert_eq!(rx.recv().unwrap(), 45);
+/// ```
+pub struct PinnedWorker<S> {
+    tx: Sender<PinnedJob<S>>,
+}
+
+impl<S> PinnedWorker<S> {
+    /// Enqueue a function to be executed on the dedicated worker, after all previously enqueued functions. It is given
+    /// exclusive access to the worker's state.
+    ///
+    /// Panics are handled according to the pool's [`PanicPolicy`]. If a job panics and the panic is caught, the state
+    /// is kept as-is and the worker continues executing jobs.
+    pub fn run<F: FnOnce(&mut S) + Send + 'static>(&self, f: F) {
+        self.tx.send(Box::new(f)).expect("Failed to enqueue pinned job");
+    }
+}
+
+pub(crate) fn run<S, I>(pool: &ThreadPool, init: I) -> PinnedWorker<S>
+where
+    S: 'static,
+    I: FnOnce() -> S + Send + 'static,
+{
+    let (tx, rx) = unbounded::<PinnedJob<S>>();
+    let shared = pool.shared.clone();
+    pool.run(move || {
+        let mut state = init();
+        // Runs until the handle is dropped
+        for f in rx {
+            if !shared.config.catch_panics() {
+                f(&mut state);
+            } else if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| f(&mut state))) {
+                unwind::handle(&shared, &*payload);
+            }
+        }
+    });
+    PinnedWorker { tx }
+}
@@ -1,46 +1,1589 @@
 use super::*;
 
-use std::cell::RefCell;
+use std::{
+    any::Any,
+    future::Future,
+    iter::FromIterator,
+    pin::Pin,
+    sync::{Condvar, Mutex, OnceLock, TryLockError, atomic::{AtomicU8, Ordering}},
+    task::{Context, Poll, Wake, Waker},
+    thread::Thread,
+};
+use crossbeam_utils::atomic::AtomicCell;
+use context::{JOB_CANCELLED, JOB_FINISHED, JOB_PANICKED, JOB_QUEUED, JOB_RUNNING};
 
 /// A handle that refers to a job that notifies on completion. It may be created with [`ThreadPool::run_recv`].
+///
+/// If the pool uses [`PanicPolicy::Propagate`], a panic in the job is returned by [`JobHandle::join`].
+///
+/// ```
+/// let pool = lagoon::ThreadPool::build()
+///     .with_panic_policy(lagoon::PanicPolicy::Propagate)
+///     .finish()
+///     .unwrap();
+///
+/// let job = pool.run_recv(|| -> u32 { panic!("oh no") });
+/// match job.join() {
+///     Err(lagoon::Error::Panicked(panic)) => assert_eq!(panic.message().as_deref(), Some("oh no")),
+///     _ => unreachable!(),
+/// }
+/// ```
+///
+/// Handles are [`Send`] and [`Sync`] when the result is [`Send`], so a handle may be shared between threads that each
+/// check on the job with methods that take `&self`.
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let (tx, rx) = std::sync::mpsc::channel::<()>();
+/// let job = Arc::new(pool.run_recv(move || { rx.recv().unwrap(); 42 }));
+///
+/// let watchers = (0..2)
+///     .map(|_| {
+///         let job = job.clone();
+///         std::thread::spawn(move || {
+///             while !job.is_completed() { std::thread::yield_now(); }
+///             *job.join_ref().unwrap()
+///         })
+///     })
+///     .collect::<Vec<_>>();
+///
+/// tx.send(()).unwrap();
+/// for watcher in watchers {
+///     assert_eq!(watcher.join().unwrap(), 42);
+/// }
+/// ```
 pub struct JobHandle<T> {
-    rx: oneshot::Receiver<T>,
-    maybe_recv: RefCell<Option<T>>,
+    // Receivers aren't `Sync`, so threads sharing the handle take turns to receive. A received result is only ever
+    // stashed by the thread holding the lock.
+    rx: Mutex<oneshot::Receiver<Result<T, Error>>>,
+    maybe_recv: OnceLock<Result<T, Error>>,
+    token: CancelToken,
 }
 
 impl<T> JobHandle<T> {
-    pub(crate) fn new(rx: oneshot::Receiver<T>) -> Self {
-        Self { rx, maybe_recv: RefCell::new(None) }
+    pub(crate) fn new() -> (HandleSender<T>, Self) { Self::with_token(CancelToken::default()) }
+
+    // Create a handle that shares its cancellation token with another handle
+    fn with_token(token: CancelToken) -> (HandleSender<T>, Self) {
+        let (tx, rx) = oneshot::channel();
+        let handle = Self { rx: Mutex::new(rx), maybe_recv: OnceLock::new(), token: token.clone() };
+        (HandleSender { tx: Some(tx), token }, handle)
+    }
+
+    // Receive the result of the job if it is available, arranging for the waker to be woken when it is otherwise
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Result<T, Error>> {
+        // The result may have already been received by `is_completed`
+        if let Some(x) = self.maybe_recv.take() {
+            return Poll::Ready(x);
+        }
+        Pin::new(self.rx()).poll(cx).map(|res| res.unwrap_or(Err(Error::JobPanicked)))
+    }
+
+    fn rx(&mut self) -> &mut oneshot::Receiver<Result<T, Error>> {
+        self.rx.get_mut().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // Receive the result of the job without blocking if it is available, stashing it until joining later
+    fn poll(&self) -> Result<(), oneshot::TryRecvError> {
+        if self.maybe_recv.get().is_none() {
+            let rx = match self.rx.try_lock() {
+                Ok(rx) => rx,
+                // Another thread is waiting for the result, so it isn't available yet
+                Err(TryLockError::WouldBlock) => return Err(oneshot::TryRecvError::Empty),
+                Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            };
+            // The result may have been stashed since we last checked
+            if self.maybe_recv.get().is_none() {
+                let _ = self.maybe_recv.set(rx.try_recv()?);
+            }
+        }
+        Ok(())
     }
 
     /// Returns whether the job associated with this handle has finished executing and can be joined without blocking.
+    ///
+    /// A job that panicked, or was dropped without being executed, counts as completed: joining it returns the error
+    /// immediately.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_panic_policy(lagoon::PanicPolicy::Propagate)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let job = pool.run_recv(|| -> u32 { panic!("oh no") });
+    /// while !job.is_completed() { std::thread::yield_now(); }
+    ///
+    /// // The job has completed, so trying to join it gives the panic rather than the handle
+    /// match job.try_join() {
+    ///     Err(lagoon::TryJoinError::Failed(lagoon::Error::Panicked(panic))) => {
+    ///         assert_eq!(panic.message().as_deref(), Some("oh no"))
+    ///     },
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
     pub fn is_completed(&self) -> bool {
-        match self.rx.try_recv() {
+        // A disconnected channel can't be waited on either, so joining it returns immediately
+        !matches!(self.poll(), Err(oneshot::TryRecvError::Empty))
+    }
+
+    /// Returns whether the job associated with this handle panicked, or `None` if it has not yet finished executing.
+    ///
+    /// The panic payload is only available from [`JobHandle::join`] if the pool uses [`PanicPolicy::Propagate`], but
+    /// panics are detected regardless of the policy.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let job = pool.run_recv(|| -> u32 { panic!("oh no") });
+    /// while job.is_panicked().is_none() { std::thread::yield_now(); }
+    /// assert_eq!(job.is_panicked(), Some(true));
+    /// assert!(matches!(job.join(), Err(lagoon::Error::JobPanicked)));
+    /// ```
+    pub fn is_panicked(&self) -> Option<bool> {
+        match self.poll() {
+            Ok(()) => Some(matches!(self.maybe_recv.get(), Some(Err(Error::Panicked(_) | Error::JobPanicked)))),
+            // The job was dropped without producing a result, which only happens when it panics
+            Err(oneshot::TryRecvError::Disconnected) => Some(true),
+            Err(oneshot::TryRecvError::Empty) => None,
+        }
+    }
+
+    /// Attempt to join the handle without blocking.
+    ///
+    /// If the job has not yet completed, the handle is given back in [`TryJoinError::Pending`] so that it may be tried
+    /// again. If the job completed but failed (for example, because it panicked), the error is returned in
+    /// [`TryJoinError::Failed`], as it would be by [`JobHandle::join`].
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let mut job = pool.run_recv(|| -> u32 { panic!("oh no") });
+    /// loop {
+    ///     job = match job.try_join() {
+    ///         Ok(_) => unreachable!(),
+    ///         Err(lagoon::TryJoinError::Pending(job)) => job,
+    ///         // A job that will never produce a result isn't mistaken for one that hasn't finished yet
+    ///         Err(lagoon::TryJoinError::Failed(err)) => break assert!(matches!(err, lagoon::Error::JobPanicked)),
+    ///     };
+    ///     std::thread::yield_now();
+    /// }
+    /// ```
+    pub fn try_join(mut self) -> Result<T, TryJoinError<T>> {
+        if !self.is_completed() {
+            return Err(TryJoinError::Pending(self));
+        }
+        match self.maybe_recv.take() {
+            Some(res) => res.map_err(TryJoinError::Failed),
+            // The channel disconnected without a result, which only happens when the job panics
+            None => Err(TryJoinError::Failed(Error::JobPanicked)),
+        }
+    }
+
+    // Attempt to join the handle without blocking, giving back the handle if the job failed or has not yet completed
+    fn try_take(mut self) -> Result<T, Self> {
+        if self.is_completed() {
+            match self.maybe_recv.take() {
+                Some(Ok(x)) => return Ok(x),
+                Some(err) => { let _ = self.maybe_recv.set(err); },
+                None => {},
+            }
+        }
+        Err(self)
+    }
+
+    /// Block the current thread, waiting for this job to complete.
+    ///
+    /// If the job panicked, [`Error::Panicked`] is returned if the pool uses [`PanicPolicy::Propagate`], and
+    /// [`Error::JobPanicked`] otherwise. If the job was dropped without being executed (for example, because it was
+    /// evicted from a full queue by [`FullQueuePolicy::DropOldest`]), [`Error::Dropped`] is returned.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Barrier};
+    ///
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(1)
+    ///     .with_queue_capacity(1)
+    ///     .with_full_queue_policy(lagoon::FullQueuePolicy::DropOldest)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// // Block the only worker, then fill the queue
+    /// let barrier = Arc::new(Barrier::new(2));
+    /// let worker_barrier = barrier.clone();
+    /// pool.run(move || { worker_barrier.wait(); });
+    /// while pool.queue_len() > 0 { std::thread::yield_now(); }
+    /// let evicted = pool.run_recv(|| 1);
+    ///
+    /// let job = pool.run_recv(|| 2);
+    /// assert!(matches!(evicted.join(), Err(lagoon::Error::Dropped)));
+    /// barrier.wait();
+    /// assert_eq!(job.join().unwrap(), 2);
+    /// ```
+    pub fn join(mut self) -> Result<T, Error> {
+        match self.maybe_recv.take() {
+            Some(x) => x,
+            // The sender always sends something before being dropped, unless the pool aborted the job's panic
+            None => self.rx.into_inner().unwrap_or_else(|err| err.into_inner()).recv().map_err(|_| Error::JobPanicked)?,
+        }
+    }
+
+    /// Cancel the job if it has not yet started, returning whether it was cancelled.
+    ///
+    /// A cancelled job is skipped when a worker gets to it, and joining its handle returns [`Error::Cancelled`]
+    /// immediately. Cancelling a job that has started (or finished) returns `false` and has no effect.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(1).finish().unwrap();
+    ///
+    /// // Occupy the only worker so that the query can't start yet
+    /// let (tx, rx) = std::sync::mpsc::channel::<()>();
+    /// pool.run(move || rx.recv().unwrap());
+    ///
+    /// let query = pool.run_recv(|| "stale results");
+    /// assert!(query.cancel());
+    /// assert!(matches!(query.join(), Err(lagoon::Error::Cancelled)));
+    ///
+    /// let query = pool.run_recv(|| "fresh results");
+    /// tx.send(()).unwrap();
+    /// query.wait();
+    /// assert!(!query.cancel());
+    /// assert_eq!(query.join().unwrap(), "fresh results");
+    /// ```
+    pub fn cancel(&self) -> bool {
+        let cancelled = self.token.transition(JOB_QUEUED, JOB_CANCELLED);
+        if cancelled {
+            self.token.cancel();
+            // The job hasn't started, so there's no result yet
+            let _ = self.maybe_recv.set(Err(Error::Cancelled));
+        }
+        cancelled
+    }
+
+    /// Returns the progress of the job. Unlike [`JobHandle::is_completed`], this distinguishes jobs that are still
+    /// queued from jobs that are running, and jobs that finished from jobs that didn't.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(1).finish().unwrap();
+    ///
+    /// let (tx, rx) = std::sync::mpsc::channel::<()>();
+    /// let blocker = pool.run_recv(move || rx.recv().unwrap());
+    /// let jobs = (0..3).map(|i| pool.run_recv(move || i)).collect::<Vec<_>>();
+    ///
+    /// while blocker.status() == lagoon::JobStatus::Queued { std::thread::yield_now(); }
+    /// assert_eq!(blocker.status(), lagoon::JobStatus::Running);
+    /// assert_eq!(jobs.iter().filter(|job| job.status() == lagoon::JobStatus::Queued).count(), 3);
+    ///
+    /// tx.send(()).unwrap();
+    /// jobs.iter().for_each(|job| job.wait());
+    /// assert!(jobs.iter().all(|job| job.status() == lagoon::JobStatus::Finished));
+    /// ```
+    pub fn status(&self) -> JobStatus {
+        match self.token.state() {
+            JOB_QUEUED => JobStatus::Queued,
+            JOB_RUNNING => JobStatus::Running,
+            JOB_FINISHED => JobStatus::Finished,
+            JOB_PANICKED => JobStatus::Panicked,
+            _ => JobStatus::Cancelled,
+        }
+    }
+
+    /// Returns an [`AbortHandle`] that may be used to cancel the job from elsewhere, while this handle keeps sole
+    /// access to the result.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let (started_tx, started) = std::sync::mpsc::channel();
+    /// let job = pool.run_recv_ctx(move |ctx| {
+    ///     started_tx.send(()).unwrap();
+    ///     while !ctx.is_cancelled() { std::thread::sleep(Duration::from_millis(1)); }
+    ///     "gave up"
+    /// });
+    ///
+    /// // A watchdog aborts the job once it has been running for too long
+    /// let abort = job.abort_handle();
+    /// std::thread::spawn(move || {
+    ///     started.recv().unwrap();
+    ///     std::thread::sleep(Duration::from_millis(10));
+    ///     abort.abort();
+    /// });
+    ///
+    /// assert_eq!(job.join().unwrap(), "gave up");
+    /// ```
+    pub fn abort_handle(&self) -> AbortHandle { AbortHandle { token: self.token.clone() } }
+
+    /// Block the current thread, waiting for this job to complete, without retrieving its result. The handle may then
+    /// be joined without blocking.
+    pub fn wait(&self) { let _ = self.join_ref(); }
+
+    /// Returns a reference to the result of the job, if it has completed successfully. This doesn't block.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let job = pool.run_recv(|| vec![1, 2, 3]);
+    /// job.wait();
+    /// assert_eq!(job.peek(), Some(&vec![1, 2, 3]));
+    /// // The result is still available afterwards
+    /// assert_eq!(job.join().unwrap(), vec![1, 2, 3]);
+    /// ```
+    pub fn peek(&self) -> Option<&T> {
+        let _ = self.poll();
+        self.maybe_recv.get()?.as_ref().ok()
+    }
+
+    /// Block the current thread, waiting for this job to complete, then return a reference to its result. Unlike
+    /// [`JobHandle::join`], the handle is left intact, so it may be joined by reference any number of times: the
+    /// result is received once and cached in the handle, so later calls return immediately. This is useful when the
+    /// handle is owned by a long-lived value.
+    ///
+    /// Errors are as with [`JobHandle::join`], except that they're returned by reference too, since the result (and
+    /// any error) stays in the handle. [`JobHandle::join`] may still be used to take the result afterwards.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let job = pool.run_recv(|| "Hello".to_string());
+    /// assert_eq!(job.join_ref().unwrap(), "Hello");
+    /// assert_eq!(job.join_ref().unwrap(), "Hello");
+    /// ```
+    pub fn join_ref(&self) -> Result<&T, &Error> {
+        if self.maybe_recv.get().is_none() {
+            let rx = self.rx.lock().unwrap_or_else(|err| err.into_inner());
+            // Another thread may have stashed the result while we waited for the lock
+            if self.maybe_recv.get().is_none() {
+                let _ = self.maybe_recv.set(rx.recv_ref().unwrap_or(Err(Error::JobPanicked)));
+            }
+        }
+        self.maybe_recv.get().expect("job result was not stashed").as_ref()
+    }
+
+    /// Transform the result of the job with the given function, returning a handle to the transformed result.
+    ///
+    /// The function is applied on the thread that joins the returned handle, once the result is available, rather
+    /// than on the pool. This is intended for cheap post-processing that isn't worth a job of its own.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let job = pool.run_recv(|| "42".to_string())
+    ///     .map(|s| s.parse::<u32>().unwrap())
+    ///     .map(|x| x * 2);
+    /// assert_eq!(job.join().unwrap(), 84);
+    /// ```
+    pub fn map<U, F: FnOnce(T) -> U + Send + 'static>(self, f: F) -> MappedJobHandle<T, U> {
+        MappedJobHandle { handle: self, f: Box::new(f) }
+    }
+
+    /// Enqueue a function to be executed as a job on the given pool once this job completes, passing it this job's
+    /// result. Returns a handle that allows retrieval of the return value of the function.
+    ///
+    /// Unlike [`JobHandle::map`], the function runs as a job of its own rather than on the thread that joins the
+    /// handle. Nothing waits on this job in the meantime: the continuation is enqueued by whichever thread completes
+    /// this job, so chains of jobs don't tie up workers (and don't deadlock on a pool with a single thread).
+    ///
+    /// If this job fails (for example, because it panicked), the function is never executed and joining the returned
+    /// handle returns the error. If the pool has been closed with [`ThreadPool::close`] by the time this job completes,
+    /// joining the returned handle returns [`Error::Dropped`]. Joining the pool with [`ThreadPool::join_all`] waits
+    /// for pending continuations to be enqueued and executed.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(1)
+    ///     .with_panic_policy(lagoon::PanicPolicy::Propagate)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let job = pool.run_recv(|| "42".to_string())
+    ///     .then(&pool, |s| s.parse::<u32>().unwrap())
+    ///     .then(&pool, |x| x * 2);
+    /// assert_eq!(job.join().unwrap(), 84);
+    ///
+    /// // Panics are passed along the chain
+    /// let job = pool.run_recv(|| -> u32 { panic!("oh no") }).then(&pool, |x| x * 2);
+    /// match job.join() {
+    ///     Err(lagoon::Error::Panicked(panic)) => assert_eq!(panic.message().as_deref(), Some("oh no")),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn then<R, F>(self, pool: &ThreadPool, f: F) -> JobHandle<R>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+        F: FnOnce(T) -> R + Send + 'static,
+    {
+        let (tx, handle) = JobHandle::new();
+        // Keep the queue open until the continuation has been enqueued
+        let pool = pool.share();
+        Continuation::start(self, Box::new(move |res| match res {
             Ok(x) => {
-                // Stash the received value until joining later
-                *self.maybe_recv.borrow_mut() = Some(x);
-                true
+                let shared = pool.shared.clone();
+                // If the job can't be enqueued, dropping it tells the handle
+                let _ = pool.try_run(move || tx.run(|| unwind::catch(&shared, move || f(x)).map_err(Error::Panicked)));
             },
-            Err(_) => false,
+            Err(err) => tx.send(Err(err)),
+        }));
+        handle
+    }
+
+    /// Arrange for the given function to be called with the result of the job once it completes, without blocking.
+    ///
+    /// This is useful for notifying code that can't block, such as an event loop, when there is no async runtime to
+    /// await the handle on. The function is called on the thread that completes the job, which is usually the worker
+    /// that executed it, immediately after the job finishes. If the job has already completed, the function is called
+    /// immediately on the current thread instead. Since it may hold up a worker, the function should not block for
+    /// long. A panic in the function is handled according to the pool's [`PanicPolicy`].
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let (events, rx) = std::sync::mpsc::channel();
+    /// pool.run_recv(|| 6 * 7).on_complete(move |res| events.send(res.unwrap()).unwrap());
+    /// assert_eq!(rx.recv().unwrap(), 42);
+    /// ```
+    pub fn on_complete<F: FnOnce(Result<T, Error>) + Send + 'static>(self, f: F)
+    where
+        T: Send + 'static,
+    {
+        Continuation::start(self, Box::new(f));
+    }
+
+    /// Combine this handle with another, producing a handle to both results.
+    ///
+    /// Neither job is waited on in the meantime: each job's result is kept as it completes, and the combined handle
+    /// completes as soon as both have. If either job fails, joining the combined handle returns its error (preferring
+    /// a panic over other kinds of failure), but only once the other job has completed too. Combined handles may be
+    /// combined further, and cancelling one doesn't cancel either job.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let audio = pool.run_recv(|| vec![0i16; 480]);
+    /// let video = pool.run_recv(|| vec![0u8; 640 * 480]);
+    /// let subtitles = pool.run_recv(|| "Hello, world!");
+    ///
+    /// let ((audio, video), subtitles) = audio.zip(video).zip(subtitles).join().unwrap();
+    /// assert_eq!((audio.len(), video.len(), subtitles), (480, 640 * 480, "Hello, world!"));
+    ///
+    /// let failed = pool.run_recv(|| 42).zip(pool.run_recv(|| -> u32 { panic!("oh no") }));
+    /// assert!(matches!(failed.join(), Err(lagoon::Error::JobPanicked)));
+    /// ```
+    pub fn zip<U: Send + 'static>(self, other: JobHandle<U>) -> JobHandle<(T, U)>
+    where
+        T: Send + 'static,
+    {
+        let (tx, handle) = JobHandle::new();
+        let zip = Arc::new(Mutex::new(Zip { a: None, b: None, tx: Some(tx) }));
+        let zip2 = zip.clone();
+        // The lock is released before sending, since sending may resume the continuations of other handles
+        Continuation::start(self, Box::new(move |res| {
+            let ready = { let mut zip = zip.lock().unwrap(); zip.a = Some(res); zip.take_ready() };
+            if let Some(zip) = ready { zip.send() }
+        }));
+        Continuation::start(other, Box::new(move |res| {
+            let ready = { let mut zip = zip2.lock().unwrap(); zip.b = Some(res); zip.take_ready() };
+            if let Some(zip) = ready { zip.send() }
+        }));
+        handle
+    }
+
+    /// Convert this handle into a channel receiver that receives the result of the job once it completes, allowing the
+    /// job to be waited on alongside other channels with [`crossbeam_channel::select!`].
+    ///
+    /// The result is forwarded to the channel as soon as the job completes, without waiting on it. Errors are as with
+    /// [`JobHandle::join`].
+    ///
+    /// ```
+    /// use crossbeam_channel::{select, unbounded};
+    ///
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let (_events_tx, events) = unbounded::<&str>();
+    /// let job = pool.run_recv(|| 6 * 7).into_receiver();
+    ///
+    /// select! {
+    ///     recv(events) -> event => panic!("unexpected event: {:?}", event),
+    ///     recv(job) -> res => assert_eq!(res.unwrap().unwrap(), 42),
+    /// }
+    /// ```
+    #[cfg(feature = "channel-crossbeam")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "channel-crossbeam")))]
+    pub fn into_receiver(self) -> crossbeam_channel::Receiver<Result<T, Error>>
+    where
+        T: Send + 'static,
+    {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        Continuation::start(self, Box::new(move |res| { let _ = tx.send(res); }));
+        rx
+    }
+
+    /// Convert this handle into a [`SharedJobHandle`], which may be cloned to allow several consumers to retrieve the
+    /// result of the job.
+    ///
+    /// The result is forwarded to the shared handle as soon as the job completes, without waiting on it. If the job
+    /// fails (for example, because it panicked), every clone observes the error.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let job = pool.run_recv(|| -> u32 { panic!("oh no") }).shared();
+    /// let consumer = {
+    ///     let job = job.clone();
+    ///     std::thread::spawn(move || job.join())
+    /// };
+    ///
+    /// assert!(matches!(*consumer.join().unwrap().unwrap_err(), lagoon::Error::JobPanicked));
+    /// assert!(matches!(*job.join().unwrap_err(), lagoon::Error::JobPanicked));
+    /// ```
+    pub fn shared(self) -> SharedJobHandle<T>
+    where
+        T: Clone + Send + 'static,
+    {
+        let (tx, handle) = SharedJobHandle::new();
+        Continuation::start(self, Box::new(move |res| tx.send(res)));
+        handle
+    }
+
+    /// Race this job against another that produces the same kind of result, producing a handle to the result of
+    /// whichever succeeds first.
+    ///
+    /// Once one job succeeds, the other is cancelled (see [`JobHandle::cancel`]) and its result is discarded when it
+    /// completes. A job that fails (for example, because it panicked) doesn't win the race: the combined handle falls
+    /// back to the other job instead, and only fails if both jobs do, in which case joining it returns the error of the
+    /// job that failed first. As with [`JobHandle::zip`], neither job is waited on in the meantime, and cancelling the
+    /// combined handle doesn't cancel either job.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(2).finish().unwrap();
+    ///
+    /// // Fetch from the cache and compute afresh, taking whichever returns first
+    /// let cached = pool.run_recv(|| "cached");
+    /// let fresh = pool.run_recv(|| { std::thread::sleep(Duration::from_millis(50)); "fresh" });
+    /// assert_eq!(cached.race(fresh).join().unwrap(), "cached");
+    ///
+    /// // A job that panics doesn't win
+    /// let broken = pool.run_recv(|| -> &str { panic!("oh no") });
+    /// let fresh = pool.run_recv(|| { std::thread::sleep(Duration::from_millis(50)); "fresh" });
+    /// assert_eq!(broken.race(fresh).join().unwrap(), "fresh");
+    ///
+    /// // ...unless both jobs fail
+    /// let broken = || pool.run_recv(|| -> &str { panic!("oh no") });
+    /// assert!(matches!(broken().race(broken()).join(), Err(lagoon::Error::JobPanicked)));
+    /// ```
+    pub fn race(self, other: JobHandle<T>) -> JobHandle<T>
+    where
+        T: Send + 'static,
+    {
+        let (tx, handle) = JobHandle::new();
+        let tokens = [self.token.clone(), other.token.clone()];
+        let race = Arc::new(Mutex::new(Race { tx: Some(tx), failed: None, tokens }));
+        for (idx, handle) in vec![self, other].into_iter().enumerate() {
+            let race = race.clone();
+            // As with `zip`, the lock is released before sending
+            Continuation::start(handle, Box::new(move |res| {
+                let ready = race.lock().unwrap().complete(idx, res);
+                if let Some((tx, res)) = ready { tx.send(res) }
+            }));
         }
+        handle
     }
 
+    /// Block the current thread, waiting for this job to complete, but give up once the given timeout has elapsed.
+    ///
+    /// If the job has not completed by then, the handle is given back in [`JoinTimeout::TimedOut`] so that it may be
+    /// waited on again later. If the job has already completed (for example, as observed by
+    /// [`JobHandle::is_completed`]), this returns immediately regardless of the timeout. Errors are as with
+    /// [`JobHandle::join`].
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let (tx, rx) = std::sync::mpsc::channel::<()>();
+    /// let job = pool.run_recv(move || { rx.recv().unwrap(); 42 });
+    ///
+    /// let job = match job.join_timeout(Duration::from_millis(10)) {
+    ///     Err(lagoon::JoinTimeout::TimedOut(job)) => job,
+    ///     _ => unreachable!(),
+    /// };
+    /// tx.send(()).unwrap();
+    /// assert_eq!(job.join_timeout(Duration::from_secs(60)).unwrap(), 42);
+    /// ```
+    pub fn join_timeout(self, timeout: Duration) -> Result<T, JoinTimeout<T>> {
+        // A timeout too large to represent is as good as no timeout at all
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.join_deadline(deadline),
+            None => self.join().map_err(JoinTimeout::Failed),
+        }
+    }
+
+    /// Block the current thread, waiting for this job to complete, but give up once the given deadline has passed.
+    ///
+    /// This behaves just like [`JobHandle::join_timeout`], but takes an absolute deadline rather than a duration. If
+    /// the deadline has already passed, this returns immediately, with the result if the job has completed.
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let pool = lagoon::ThreadPool::default();
+    ///
+    /// let frame_end = Instant::now() + Duration::from_millis(16);
+    /// let job = pool.run_recv(|| std::thread::sleep(Duration::from_secs(1)));
+    ///
+    /// // The job can't finish in time
+    /// assert!(matches!(job.join_deadline(frame_end), Err(lagoon::JoinTimeout::TimedOut(_))));
+    /// ```
+    pub fn join_deadline(mut self, deadline: Instant) -> Result<T, JoinTimeout<T>> {
+        let res = match self.maybe_recv.take() {
+            Some(x) => x,
+            None => match self.rx().recv_deadline(deadline) {
+                Ok(x) => x,
+                Err(oneshot::RecvTimeoutError::Timeout) => return Err(JoinTimeout::TimedOut(self)),
+                Err(oneshot::RecvTimeoutError::Disconnected) => Err(Error::JobPanicked),
+            },
+        };
+        res.map_err(JoinTimeout::Failed)
+    }
+}
+
+/// The progress of a job with a handle, returned by [`JobHandle::status`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum JobStatus {
+    /// The job is waiting for a worker to execute it. A continuation created with [`JobHandle::then`] is also queued
+    /// while it waits for the job it continues from.
+    Queued,
+    /// The job is being executed.
+    Running,
+    /// The job finished executing.
+    Finished,
+    /// The job panicked.
+    Panicked,
+    /// The job will never be executed, because it was cancelled, skipped, or dropped from the queue.
+    Cancelled,
+}
+
+/// A handle that may be used to cancel a job, but not to retrieve its result. It may be created with
+/// [`JobHandle::abort_handle`].
+///
+/// Unlike a [`JobHandle`], an abort handle may be cloned and shared between threads, so that one component can wait on
+/// the result of a job while another (such as a watchdog) decides whether to give up on it.
+#[derive(Clone, Debug)]
+pub struct AbortHandle {
+    token: CancelToken,
+}
+
+impl AbortHandle {
+    /// Request that the job be cancelled.
+    ///
+    /// If the job has not yet started, it is skipped when a worker gets to it, and joining its handle returns
+    /// [`Error::Cancelled`]. A job that has already started keeps running, but a job enqueued with
+    /// [`ThreadPool::run_recv_ctx`] may observe the request with [`JobContext::is_cancelled`] and stop early.
+    pub fn abort(&self) { self.token.cancel() }
+
+    /// Returns whether cancellation of the job has been requested, either with [`AbortHandle::abort`] or
+    /// [`JobHandle::cancel`].
+    pub fn is_aborted(&self) -> bool { self.token.is_cancelled() }
+}
+
+type ContinuationFn<T> = Box<dyn FnOnce(Result<T, Error>) + Send>;
+
+// A function waiting on the result of a job (see `JobHandle::on_complete`). It's woken by the job's sender when the
+// result is sent, so no thread has to wait for the job to complete.
+struct Continuation<T> {
+    state: Mutex<Option<(JobHandle<T>, ContinuationFn<T>)>>,
+}
+
+impl<T: Send + 'static> Continuation<T> {
+    fn start(handle: JobHandle<T>, f: ContinuationFn<T>) {
+        Arc::new(Self { state: Mutex::new(Some((handle, f))) }).resume();
+    }
+
+    // Call the function if the result is available, or wait to be woken again otherwise
+    fn resume(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        let res = match &mut *state {
+            Some((handle, _)) => match handle.poll_recv(&mut Context::from_waker(&Waker::from(self.clone()))) {
+                Poll::Ready(res) => res,
+                Poll::Pending => return,
+            },
+            // Already called
+            None => return,
+        };
+        let (_, f) = state.take().unwrap();
+        drop(state);
+        f(res);
+    }
+}
+
+impl<T: Send + 'static> Wake for Continuation<T> {
+    fn wake(self: Arc<Self>) { self.resume() }
+}
+
+/// Block the current thread until any one of the given jobs completes, returning its result along with the handles to
+/// the remaining jobs (in their original order).
+///
+/// Nothing spins in the meantime: the first job to complete wakes the current thread. The remaining handles are
+/// replaced with new handles to the same jobs, which receive their results as they complete.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::build().with_thread_count(2).finish().unwrap();
+///
+/// let (tx, rx) = std::sync::mpsc::channel::<()>();
+/// let jobs = vec![
+///     pool.run_recv(move || { rx.recv().unwrap(); "slow" }),
+///     pool.run_recv(|| "fast"),
+/// ];
+///
+/// let (first, rest) = lagoon::join_any(jobs);
+/// assert_eq!(first, "fast");
+/// tx.send(()).unwrap();
+/// assert_eq!(rest.into_iter().next().unwrap().join().unwrap(), "slow");
+/// ```
+///
+/// # Panics
+///
+/// This function panics if no handles are given, or if the first job to complete failed (in which case its panic is
+/// resumed, if it panicked and the pool uses [`PanicPolicy::Propagate`]).
+pub fn join_any<T: Send + 'static>(handles: Vec<JobHandle<T>>) -> (T, Vec<JobHandle<T>>) {
+    assert!(!handles.is_empty(), "join_any requires at least one handle");
+    match select(handles) {
+        (Ok(x), handles) => (x, handles),
+        (Err(Error::Panicked(panic)), _) => panic.resume(),
+        (Err(err), _) => panic!("a job passed to join_any failed: {}", err),
+    }
+}
+
+/// Block the current thread until any one of the given jobs completes, returning its result (whether it succeeded or
+/// failed) along with the handles to the remaining jobs (in their original order).
+///
+/// As with [`join_any`], the current thread is woken by the first job to complete, and the remaining handles are
+/// replaced with new handles to the same jobs.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::build().with_thread_count(2).finish().unwrap();
+///
+/// // Ask two backends the same question, and take whichever answers first
+/// let (tx, rx) = std::sync::mpsc::channel::<()>();
+/// let queries = vec![
+///     pool.run_recv(move || -> Result<&str, &str> { rx.recv().unwrap(); Ok("slow answer") }),
+///     pool.run_recv(|| Ok("fast answer")),
+/// ];
+///
+/// let (first, rest) = lagoon::select(queries);
+/// assert_eq!(first.unwrap(), Ok("fast answer"));
+/// assert_eq!(rest.len(), 1);
+/// tx.send(()).unwrap();
+/// ```
+///
+/// # Panics
+///
+/// This function panics if no handles are given.
+pub fn select<T: Send + 'static>(handles: Vec<JobHandle<T>>) -> Selected<T> {
+    assert!(!handles.is_empty(), "select requires at least one handle");
+    match select_deadline(handles, None) {
+        Ok(first) => first,
+        // Without a deadline, we only stop waiting once a job has completed
+        Err(_) => unreachable!(),
+    }
+}
+
+/// Block the current thread until any one of the given jobs completes or the timeout elapses, whichever happens first.
+///
+/// If a job completes in time, its result is returned along with the handles to the remaining jobs, as with
+/// [`select`]. Otherwise, the handles to all of the jobs are given back in their original order so that they may be
+/// waited on again.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let (tx, rx) = std::sync::mpsc::channel::<()>();
+/// let queries = vec![pool.run_recv(move || { rx.recv().unwrap(); "answer" })];
+///
+/// let queries = match lagoon::select_timeout(queries, Duration::from_millis(10)) {
+///     Ok(_) => unreachable!(),
+///     Err(queries) => queries,
+/// };
+/// tx.send(()).unwrap();
+/// match lagoon::select_timeout(queries, Duration::from_secs(10)) {
+///     Ok((first, _)) => assert_eq!(first.unwrap(), "answer"),
+///     Err(_) => unreachable!(),
+/// }
+/// ```
+///
+/// # Panics
+///
+/// This function panics if no handles are given.
+pub fn select_timeout<T: Send + 'static>(
+    handles: Vec<JobHandle<T>>,
+    timeout: Duration,
+) -> Result<Selected<T>, Vec<JobHandle<T>>> {
+    assert!(!handles.is_empty(), "select_timeout requires at least one handle");
+    select_deadline(handles, Instant::now().checked_add(timeout))
+}
+
+// The result of the first job to complete, along with the handles to the remaining jobs
+type Selected<T> = (Result<T, Error>, Vec<JobHandle<T>>);
+
+// Wait for the first of the given jobs to complete, or for the deadline to pass (in which case every handle is given
+// back). Nothing spins in the meantime: each job's result is forwarded by a continuation, and the first to complete
+// sends its result to the waiting thread while the rest are passed on to new handles.
+fn select_deadline<T: Send + 'static>(
+    handles: Vec<JobHandle<T>>,
+    deadline: Option<Instant>,
+) -> Result<Selected<T>, Vec<JobHandle<T>>> {
+    let (tx, rx) = bounded(1);
+    // The first job to complete takes the sender, so only one result is ever sent to the waiting thread
+    let winner = Arc::new(Mutex::new(Some(tx)));
+    let mut handles = handles
+        .into_iter()
+        .enumerate()
+        .map(|(idx, handle)| {
+            // Cancelling the new handle cancels the job, as before
+            let (fwd_tx, fwd) = JobHandle::with_token(handle.token.clone());
+            let winner = winner.clone();
+            Continuation::start(handle, Box::new(move |res| {
+                let tx = winner.lock().unwrap().take();
+                match tx {
+                    Some(tx) => { let _ = tx.send((idx, res)); },
+                    None => fwd_tx.send(res),
+                }
+            }));
+            fwd
+        })
+        .collect::<Vec<_>>();
+
+    let first = match deadline {
+        Some(deadline) => rx.recv_deadline(deadline).ok(),
+        None => rx.recv().ok(),
+    };
+    // Taking the sender ourselves stops any job from winning from now on, but one may have won in the meantime
+    let first = first.or_else(|| match winner.lock().unwrap().take() {
+        Some(_) => None,
+        None => rx.recv().ok(),
+    });
+    match first {
+        Some((idx, res)) => {
+            handles.remove(idx);
+            Ok((res, handles))
+        },
+        None => Err(handles),
+    }
+}
+
+/// Block the current thread until all of the given jobs complete, returning their results in the same order as the
+/// handles, or the first error encountered.
+///
+/// Handles are joined in order, but since every job must complete anyway, this takes no longer than joining them in
+/// the order that they complete. A job that panics or is dropped completes with an error, so it never causes a hang.
+/// If a job fails, the remaining handles are dropped without being joined: use [`join_all_results`] to keep the
+/// results of the other jobs instead.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let jobs = (0..10u64).map(|i| pool.run_recv(move || i * i));
+/// assert_eq!(lagoon::join_all(jobs).unwrap(), (0..10u64).map(|i| i * i).collect::<Vec<_>>());
+/// ```
+pub fn join_all<T>(handles: impl IntoIterator<Item = JobHandle<T>>) -> Result<Vec<T>, Error> {
+    handles.into_iter().map(JobHandle::join).collect()
+}
+
+/// Block the current thread until all of the given jobs complete, returning the result of each in the same order as
+/// the handles.
+///
+/// Unlike [`join_all`], a failed job doesn't discard the results of the others.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let jobs = vec![pool.run_recv(|| 1u32), pool.run_recv(|| panic!("oh no")), pool.run_recv(|| 3)];
+/// let results = lagoon::join_all_results(jobs);
+/// assert_eq!(results[0].as_ref().ok(), Some(&1));
+/// assert!(matches!(results[1], Err(lagoon::Error::JobPanicked)));
+/// assert_eq!(results[2].as_ref().ok(), Some(&3));
+/// ```
+pub fn join_all_results<T>(handles: impl IntoIterator<Item = JobHandle<T>>) -> Vec<Result<T, Error>> {
+    handles.into_iter().map(JobHandle::join).collect()
+}
+
+/// A collection of handles to jobs, whose results may be taken in the order that the jobs complete.
+///
+/// Every job's result is forwarded to the set as soon as the job completes, so waiting on the set never polls
+/// individual handles. A job that fails (for example, because it panicked) produces an error rather than disappearing
+/// from the set.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let mut jobs = (0..10u64).map(|i| pool.run_recv(move || i * i)).collect::<lagoon::JobSet<_>>();
+/// jobs.insert(pool.run_recv(|| panic!("oh no")));
+/// assert_eq!(jobs.len(), 11);
+///
+/// let (squares, failed): (Vec<_>, Vec<_>) = jobs.as_completed().partition(|res| res.is_ok());
+/// assert_eq!(squares.into_iter().map(Result::unwrap).sum::<u64>(), (0..10u64).map(|i| i * i).sum());
+/// assert!(matches!(failed[..], [Err(lagoon::Error::JobPanicked)]));
+/// assert!(jobs.is_empty());
+/// ```
+pub struct JobSet<T> {
+    tx: Sender<Result<T, Error>>,
+    rx: Receiver<Result<T, Error>>,
+    // The number of jobs whose results have not yet been taken from the set
+    len: usize,
+}
+
+impl<T> JobSet<T> {
+    /// Create a new, empty set.
+    pub fn new() -> Self {
+        let (tx, rx) = unbounded();
+        Self { tx, rx, len: 0 }
+    }
+
+    /// Returns the number of jobs in the set whose results have not yet been taken, whether or not they've completed.
+    pub fn len(&self) -> usize { self.len }
+
+    /// Returns whether the set has no jobs whose results have not yet been taken.
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Returns the number of jobs in the set that have completed, but whose results have not yet been taken.
+    pub fn completed(&self) -> usize { self.rx.len() }
+
+    /// Returns an iterator that blocks the current thread until each job in the set completes, taking the results in
+    /// the order that the jobs complete. The iterator ends once the set is empty.
+    pub fn as_completed(&mut self) -> AsCompleted<'_, T> { AsCompleted { set: self } }
+
+    /// Block the current thread until every job in the set completes, returning their results in the order that the
+    /// jobs completed.
+    pub fn wait_all(mut self) -> Vec<Result<T, Error>> { self.as_completed().collect() }
+}
+
+impl<T: Send + 'static> JobSet<T> {
+    /// Add a job to the set.
+    pub fn insert(&mut self, handle: JobHandle<T>) {
+        let tx = self.tx.clone();
+        Continuation::start(handle, Box::new(move |res| { let _ = tx.send(res); }));
+        self.len += 1;
+    }
+}
+
+impl<T> Default for JobSet<T> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<T: Send + 'static> Extend<JobHandle<T>> for JobSet<T> {
+    fn extend<I: IntoIterator<Item = JobHandle<T>>>(&mut self, handles: I) {
+        handles.into_iter().for_each(|handle| self.insert(handle));
+    }
+}
+
+impl<T: Send + 'static> FromIterator<JobHandle<T>> for JobSet<T> {
+    fn from_iter<I: IntoIterator<Item = JobHandle<T>>>(handles: I) -> Self {
+        let mut set = Self::new();
+        set.extend(handles);
+        set
+    }
+}
+
+/// An iterator over the results of the jobs in a [`JobSet`], in the order that the jobs complete. It may be created
+/// with [`JobSet::as_completed`].
+pub struct AsCompleted<'a, T> {
+    set: &'a mut JobSet<T>,
+}
+
+impl<'a, T> Iterator for AsCompleted<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.set.len == 0 {
+            return None;
+        }
+        self.set.len -= 1;
+        // Every job sends its result eventually, and the set holds a sender, so the channel can't disconnect
+        Some(self.set.rx.recv().expect("job set channel disconnected"))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { (self.set.len, Some(self.set.len)) }
+}
+
+/// A handle that refers to a job whose result is transformed by a function when it is joined. It may be created with
+/// [`JobHandle::map`].
+pub struct MappedJobHandle<T, U> {
+    handle: JobHandle<T>,
+    f: Box<dyn FnOnce(T) -> U + Send>,
+}
+
+impl<T, U> MappedJobHandle<T, U> {
+    /// Returns whether the job associated with this handle has finished executing and can be joined without blocking.
+    pub fn is_completed(&self) -> bool { self.handle.is_completed() }
+
+    /// Returns whether the job associated with this handle panicked, or `None` if it has not yet finished executing.
+    pub fn is_panicked(&self) -> Option<bool> { self.handle.is_panicked() }
+
     /// Attempt to join the handle without blocking, returning an `Err` containing the handle if unsuccessful.
-    pub fn try_join(self) -> Result<T, Self> {
-        let x = self.maybe_recv.borrow_mut().take();
-        if let Some(x) = x {
-            Ok(x)
+    ///
+    /// If the job failed (for example, because it panicked), the handle is returned and the error may be retrieved with
+    /// [`MappedJobHandle::join`].
+    pub fn try_join(self) -> Result<U, Self> {
+        match self.handle.try_take() {
+            Ok(x) => Ok((self.f)(x)),
+            Err(handle) => Err(Self { handle, f: self.f }),
+        }
+    }
+
+    /// Block the current thread, waiting for this job to complete, then transform its result. Errors are as with
+    /// [`JobHandle::join`].
+    pub fn join(self) -> Result<U, Error> { self.handle.join().map(self.f) }
+
+    /// Transform the result of the job further with the given function, after the functions already applied.
+    pub fn map<V, G: FnOnce(U) -> V + Send + 'static>(self, g: G) -> MappedJobHandle<T, V>
+    where
+        T: 'static,
+        U: 'static,
+    {
+        let f = self.f;
+        MappedJobHandle { handle: self.handle, f: Box::new(move |x| g(f(x))) }
+    }
+}
+
+// The results of two jobs being combined by `JobHandle::zip`, as they complete
+struct Zip<T, U> {
+    a: Option<Result<T, Error>>,
+    b: Option<Result<U, Error>>,
+    tx: Option<HandleSender<(T, U)>>,
+}
+
+impl<T, U> Zip<T, U> {
+    // If both jobs have completed, take their results along with the sender
+    fn take_ready(&mut self) -> Option<Self> {
+        if self.a.is_none() || self.b.is_none() {
+            return None;
+        }
+        Some(Self { a: self.a.take(), b: self.b.take(), tx: self.tx.take() })
+    }
+
+    // Send the combined result of both jobs, which must have completed
+    fn send(self) {
+        let is_panic = |err: &Error| matches!(err, Error::Panicked(_) | Error::JobPanicked);
+        let res = match (self.a.expect("zipped job not complete"), self.b.expect("zipped job not complete")) {
+            (Ok(a), Ok(b)) => Ok((a, b)),
+            (Err(a), Err(b)) if !is_panic(&a) && is_panic(&b) => Err(b),
+            (Err(err), _) | (_, Err(err)) => Err(err),
+        };
+        if let Some(tx) = self.tx {
+            tx.send(res);
+        }
+    }
+}
+
+// The state of two jobs being raced by `JobHandle::race`
+struct Race<T> {
+    // Taken by the job that decides the result, so that the other's result is discarded
+    tx: Option<HandleSender<T>>,
+    failed: Option<Error>,
+    tokens: [CancelToken; 2],
+}
+
+impl<T> Race<T> {
+    // Record the result of one of the jobs. If this decides the race, returns the result along with the sender to send
+    // it to.
+    fn complete(&mut self, idx: usize, res: Result<T, Error>) -> Option<(HandleSender<T>, Result<T, Error>)> {
+        match res {
+            Ok(x) => {
+                let tx = self.tx.take()?;
+                self.tokens[1 - idx].cancel();
+                Some((tx, Ok(x)))
+            },
+            // The other job may still succeed
+            Err(err) if self.tx.is_some() && self.failed.is_none() => {
+                self.failed = Some(err);
+                None
+            },
+            Err(_) => Some((self.tx.take()?, Err(self.failed.take()?))),
+        }
+    }
+}
+
+// The result is never pinned, since it's moved out of the handle as soon as it's received
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+impl<T> Unpin for JobHandle<T> {}
+
+/// Waiting on a handle asynchronously, rather than blocking the current thread with [`JobHandle::join`]. The output is
+/// the same as that of [`JobHandle::join`].
+///
+/// ```
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let job = pool.run_recv(|| (1..=10u64).product::<u64>());
+/// assert_eq!(job.await.unwrap(), 3628800);
+/// # });
+/// ```
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+impl<T> std::future::Future for JobHandle<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> { self.get_mut().poll_recv(cx) }
+}
+
+/// The error returned by [`JobHandle::join_timeout`] and [`JobHandle::join_deadline`].
+pub enum JoinTimeout<T> {
+    /// The job did not complete in time. The handle is given back so that it may be waited on again.
+    TimedOut(JobHandle<T>),
+    /// The job completed, but failed (see [`JobHandle::join`]).
+    Failed(Error),
+}
+
+impl<T> fmt::Debug for JoinTimeout<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TimedOut(_) => f.debug_tuple("TimedOut").finish_non_exhaustive(),
+            Self::Failed(err) => f.debug_tuple("Failed").field(err).finish(),
+        }
+    }
+}
+
+impl<T> fmt::Display for JoinTimeout<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TimedOut(_) => write!(f, "timed out waiting for the job to complete"),
+            Self::Failed(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl<T> error::Error for JoinTimeout<T> {}
+
+/// The error returned by [`JobHandle::try_join`].
+pub enum TryJoinError<T> {
+    /// The job has not yet completed. The handle is given back so that it may be tried again.
+    Pending(JobHandle<T>),
+    /// The job completed, but failed (see [`JobHandle::join`]).
+    Failed(Error),
+}
+
+impl<T> fmt::Debug for TryJoinError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pending(_) => f.debug_tuple("Pending").finish_non_exhaustive(),
+            Self::Failed(err) => f.debug_tuple("Failed").field(err).finish(),
+        }
+    }
+}
+
+impl<T> fmt::Display for TryJoinError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pending(_) => write!(f, "the job has not yet completed"),
+            Self::Failed(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl<T> error::Error for TryJoinError<T> {}
+
+// The sending half of a job handle. If dropped without sending, the handle is told why: either the job panicked (and
+// the panic was caught without keeping its payload), or the job was dropped without being executed.
+pub(crate) struct HandleSender<T> {
+    tx: Option<oneshot::Sender<Result<T, Error>>>,
+    token: CancelToken,
+}
+
+impl<T> HandleSender<T> {
+    pub(crate) fn send(mut self, res: Result<T, Error>) { self.finish(res) }
+
+    fn finish(&mut self, res: Result<T, Error>) {
+        if let Some(tx) = self.tx.take() {
+            // Update the status first, so that it's up to date by the time the handle sees the result
+            self.token.set_state(match &res {
+                Ok(_) => JOB_FINISHED,
+                Err(Error::Panicked(_) | Error::JobPanicked) => JOB_PANICKED,
+                Err(_) => JOB_CANCELLED,
+            });
+            let _ = tx.send(res);
+        }
+    }
+
+    // Produce the result of the job and send it, unless the job has been cancelled
+    pub(crate) fn run(self, f: impl FnOnce() -> Result<T, Error>) {
+        if self.token.transition(JOB_QUEUED, JOB_RUNNING) {
+            self.send(f());
         } else {
-            self.rx.try_recv().map_err(|_| self)
+            self.send(Err(Error::Cancelled));
+        }
+    }
+
+    pub(crate) fn token(&self) -> &CancelToken { &self.token }
+}
+
+impl<T> Drop for HandleSender<T> {
+    fn drop(&mut self) { self.finish(Err(if thread::panicking() { Error::JobPanicked } else { Error::Dropped })) }
+}
+
+/// A handle that refers to a job with a type-erased result. It may be created with [`ThreadPool::run_recv_boxed`].
+///
+/// This is useful for storing handles to jobs with different result types in the same container. Results must be
+/// downcast to their original type after joining.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let jobs = vec![
+///     pool.run_recv_boxed(|| 42u32),
+///     pool.run_recv_boxed(|| "Hello, world!"),
+/// ];
+///
+/// let mut results = jobs.into_iter().map(|job| job.join().unwrap());
+/// assert_eq!(results.next().unwrap().downcast_ref::<u32>(), Some(&42));
+/// assert_eq!(results.next().unwrap().downcast_ref::<&str>(), Some(&"Hello, world!"));
+/// ```
+pub struct BoxedJobHandle {
+    inner: JobHandle<Box<dyn Any + Send>>,
+}
+
+impl BoxedJobHandle {
+    pub(crate) fn new(inner: JobHandle<Box<dyn Any + Send>>) -> Self {
+        Self { inner }
+    }
+
+    /// Returns whether the job associated with this handle has finished executing and can be joined without blocking.
+    pub fn is_completed(&self) -> bool { self.inner.is_completed() }
+
+    /// Returns whether the job associated with this handle panicked, or `None` if it has not yet finished executing.
+    pub fn is_panicked(&self) -> Option<bool> { self.inner.is_panicked() }
+
+    /// Attempt to join the handle without blocking, returning an `Err` containing the handle if unsuccessful.
+    pub fn try_join(self) -> Result<Box<dyn Any + Send>, Self> {
+        self.inner.try_take().map_err(Self::new)
+    }
+
+    /// Block the current thread, waiting for this job to complete.
+    pub fn join(self) -> Result<Box<dyn Any + Send>, Error> { self.inner.join() }
+}
+
+/// Waiting on a handle asynchronously, as with [`JobHandle`].
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+impl std::future::Future for BoxedJobHandle {
+    type Output = Result<Box<dyn Any + Send>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> { self.get_mut().inner.poll_recv(cx) }
+}
+
+const PENDING: u8 = 0;
+const FINISHED: u8 = 1;
+const ABANDONED: u8 = 2;
+const PANICKED: u8 = 3;
+const DROPPED: u8 = 4;
+
+// An inline result slot for small values that avoids the allocation of a separate channel
+struct SmallSlot<T> {
+    value: AtomicCell<Option<T>>,
+    panic: Mutex<Option<Panic>>,
+    state: AtomicU8,
+    waiter: Mutex<Option<Thread>>,
+}
+
+impl<T> SmallSlot<T> {
+    fn complete(&self, state: u8) {
+        self.state.store(state, Ordering::SeqCst);
+        if let Some(waiter) = self.waiter.lock().unwrap().take() {
+            waiter.unpark();
+        }
+    }
+}
+
+// The sending half of a small slot. If dropped without sending (i.e: because the job panicked, or was dropped without
+// being executed), the slot is marked as such so that joiners don't wait forever.
+pub(crate) struct SmallSender<T>(Arc<SmallSlot<T>>);
+
+impl<T> SmallSender<T> {
+    pub(crate) fn send(self, res: Result<T, Panic>) {
+        match res {
+            Ok(x) => {
+                self.0.value.store(Some(x));
+                self.0.complete(FINISHED);
+            },
+            Err(panic) => {
+                *self.0.panic.lock().unwrap() = Some(panic);
+                self.0.complete(PANICKED);
+            },
+        }
+    }
+}
+
+impl<T> Drop for SmallSender<T> {
+    fn drop(&mut self) {
+        if self.0.state.load(Ordering::SeqCst) == PENDING {
+            self.0.complete(if thread::panicking() { ABANDONED } else { DROPPED });
+        }
+    }
+}
+
+/// A handle that refers to a job with a small [`Copy`] result that notifies on completion. It may be created with
+/// [`ThreadPool::run_recv_small`].
+///
+/// This behaves just like [`JobHandle`], but stores the result inline in a shared atomic cell instead of allocating a
+/// channel for it.
+pub struct SmallJobHandle<T> {
+    slot: Arc<SmallSlot<T>>,
+}
+
+impl<T: Copy> SmallJobHandle<T> {
+    pub(crate) fn new() -> (SmallSender<T>, Self) {
+        let slot = Arc::new(SmallSlot {
+            value: AtomicCell::new(None),
+            panic: Mutex::new(None),
+            state: AtomicU8::new(PENDING),
+            waiter: Mutex::new(None),
+        });
+        (SmallSender(slot.clone()), Self { slot })
+    }
+
+    /// Returns whether the job associated with this handle has finished executing and can be joined without blocking.
+    ///
+    /// As with [`JobHandle::is_completed`], a job that panicked or was dropped counts as completed.
+    pub fn is_completed(&self) -> bool { self.slot.state.load(Ordering::SeqCst) != PENDING }
+
+    /// Returns whether the job associated with this handle panicked, or `None` if it has not yet finished executing.
+    pub fn is_panicked(&self) -> Option<bool> {
+        match self.slot.state.load(Ordering::SeqCst) {
+            PENDING => None,
+            FINISHED | DROPPED => Some(false),
+            _ => Some(true),
+        }
+    }
+
+    /// Attempt to join the handle without blocking, returning an `Err` containing the handle if unsuccessful.
+    ///
+    /// If the job panicked, the handle is returned and the panic may be retrieved with [`SmallJobHandle::join`].
+    pub fn try_join(self) -> Result<T, Self> {
+        match self.slot.value.load() {
+            Some(x) if self.slot.state.load(Ordering::SeqCst) == FINISHED => Ok(x),
+            _ => Err(self),
         }
     }
 
     /// Block the current thread, waiting for this job to complete.
     pub fn join(self) -> Result<T, Error> {
-        if let Some(x) = self.maybe_recv.borrow_mut().take() {
-            Ok(x)
-        } else {
-            self.rx.recv().map_err(|_| Error::Timeout)
+        // Register ourselves before checking the state so that completion can't slip between the check and parking
+        *self.slot.waiter.lock().unwrap() = Some(thread::current());
+        loop {
+            match self.slot.state.load(Ordering::SeqCst) {
+                FINISHED => break Ok(self.slot.value.load().expect("finished slot has no value")),
+                ABANDONED => break Err(Error::JobPanicked),
+                DROPPED => break Err(Error::Dropped),
+                PANICKED => {
+                    let panic = self.slot.panic.lock().unwrap().take().expect("panicked slot has no panic");
+                    break Err(Error::Panicked(panic));
+                },
+                _ => thread::park(),
+            }
+        }
+    }
+}
+
+/// A reusable slot that receives the result of a job. Jobs may be enqueued into it with
+/// [`ThreadPool::run_recv_into`].
+///
+/// Unlike [`ThreadPool::run_recv`], which allocates a new channel for every job, a slot is allocated once and may be
+/// reused for any number of jobs, one at a time: joining the slot retrieves the result of its current job and leaves it
+/// empty, ready for the next one. This is an optimisation for tight loops that repeatedly enqueue a job and wait for
+/// its result.
+///
+/// ```
+/// let pool = lagoon::ThreadPool::default();
+/// let slot = lagoon::ResultSlot::new();
+///
+/// for i in 0..100u32 {
+///     pool.run_recv_into(&slot, move || i * 2);
+///     assert_eq!(slot.join().unwrap(), i * 2);
+/// }
+/// ```
+pub struct ResultSlot<T> {
+    inner: Arc<SlotInner<T>>,
+}
+
+struct SlotInner<T> {
+    state: Mutex<SlotState<T>>,
+    completed: Condvar,
+}
+
+enum SlotState<T> {
+    Empty,
+    Pending,
+    Done(Result<T, Error>),
+}
+
+impl<T> Default for ResultSlot<T> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<T> ResultSlot<T> {
+    /// Create a new, empty slot.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(SlotInner {
+                state: Mutex::new(SlotState::Empty),
+                completed: Condvar::new(),
+            }),
+        }
+    }
+
+    // Mark the slot as in use by a new job, returning the means to complete it
+    pub(crate) fn sender(&self) -> SlotSender<T> {
+        let mut state = self.inner.state.lock().unwrap();
+        assert!(matches!(*state, SlotState::Empty), "result slot is already in use by another job");
+        *state = SlotState::Pending;
+        SlotSender(Some(self.inner.clone()))
+    }
+
+    /// Returns whether the slot is empty, i.e: it is not in use by a job, or the result of its last job has already
+    /// been retrieved.
+    pub fn is_empty(&self) -> bool { matches!(*self.inner.state.lock().unwrap(), SlotState::Empty) }
+
+    /// Returns whether the job using this slot has finished executing and can be joined without blocking.
+    pub fn is_completed(&self) -> bool { matches!(*self.inner.state.lock().unwrap(), SlotState::Done(_)) }
+
+    /// Block the current thread, waiting for the job using this slot to complete, then empty the slot so that it may
+    /// be reused.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the slot is empty.
+    pub fn join(&self) -> Result<T, Error> {
+        let mut state = self.inner.state.lock().unwrap();
+        loop {
+            match std::mem::replace(&mut *state, SlotState::Empty) {
+                SlotState::Empty => panic!("attempted to join an empty result slot"),
+                SlotState::Pending => {
+                    *state = SlotState::Pending;
+                    state = self.inner.completed.wait(state).unwrap();
+                },
+                SlotState::Done(x) => break x,
+            }
+        }
+    }
+}
+
+// The sending half of a result slot. If dropped without sending (i.e: because the job panicked, or was dropped without
+// being executed), the slot is completed with an error so that joiners don't wait forever.
+pub(crate) struct SlotSender<T>(Option<Arc<SlotInner<T>>>);
+
+impl<T> SlotSender<T> {
+    pub(crate) fn send(mut self, res: Result<T, Error>) {
+        if let Some(inner) = self.0.take() {
+            *inner.state.lock().unwrap() = SlotState::Done(res);
+            inner.completed.notify_all();
+        }
+    }
+}
+
+impl<T> Drop for SlotSender<T> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.0.take() {
+            let err = if thread::panicking() { Error::JobPanicked } else { Error::Dropped };
+            *inner.state.lock().unwrap_or_else(|err| err.into_inner()) = SlotState::Done(Err(err));
+            inner.completed.notify_all();
+        }
+    }
+}
+
+/// A handle that refers to a job whose result may be retrieved by several consumers. It may be created with
+/// [`ThreadPool::run_recv_shared`], or from an existing handle with [`JobHandle::shared`].
+///
+/// Cloning the handle gives another handle to the same job, and joining any of them returns a clone of the job's
+/// result. Since errors can't be cloned, they are shared through an [`Arc`].
+///
+/// ```
+/// let pool = lagoon::ThreadPool::default();
+///
+/// let job = pool.run_recv_shared(|| "Hello, world!".to_string());
+/// let consumers = (0..4)
+///     .map(|_| {
+///         let job = job.clone();
+///         std::thread::spawn(move || job.join().unwrap())
+///     })
+///     .collect::<Vec<_>>();
+///
+/// for consumer in consumers {
+///     assert_eq!(consumer.join().unwrap(), "Hello, world!");
+/// }
+/// assert_eq!(job.join().unwrap(), "Hello, world!");
+/// ```
+pub struct SharedJobHandle<T> {
+    inner: Arc<SharedInner<T>>,
+}
+
+struct SharedInner<T> {
+    result: Mutex<Option<Result<T, Arc<Error>>>>,
+    completed: Condvar,
+}
+
+impl<T> Clone for SharedJobHandle<T> {
+    fn clone(&self) -> Self { Self { inner: self.inner.clone() } }
+}
+
+impl<T: Clone> SharedJobHandle<T> {
+    pub(crate) fn new() -> (SharedSender<T>, Self) {
+        let inner = Arc::new(SharedInner { result: Mutex::new(None), completed: Condvar::new() });
+        (SharedSender(Some(inner.clone())), Self { inner })
+    }
+
+    /// Returns whether the job associated with this handle has finished executing and can be joined without blocking.
+    pub fn is_completed(&self) -> bool { self.inner.result.lock().unwrap().is_some() }
+
+    /// Attempt to join the handle without blocking, returning an `Err` containing the handle if unsuccessful.
+    ///
+    /// If the job failed (for example, because it panicked), the handle is returned and the error may be retrieved with
+    /// [`SharedJobHandle::join`].
+    pub fn try_join(self) -> Result<T, Self> {
+        let x = self.inner.result.lock().unwrap().clone();
+        match x {
+            Some(Ok(x)) => Ok(x),
+            _ => Err(self),
+        }
+    }
+
+    /// Block the current thread, waiting for this job to complete, then return a clone of its result. Errors are as
+    /// with [`JobHandle::join`].
+    pub fn join(self) -> Result<T, Arc<Error>> {
+        let result = self.inner.result.lock().unwrap();
+        let result = self.inner.completed.wait_while(result, |result| result.is_none()).unwrap();
+        result.clone().expect("completed shared handle has no result")
+    }
+}
+
+// The sending half of a shared handle. If dropped without sending (i.e: because the job panicked, or was dropped
+// without being executed), the handle is completed with an error so that joiners don't wait forever.
+pub(crate) struct SharedSender<T>(Option<Arc<SharedInner<T>>>);
+
+impl<T> SharedSender<T> {
+    pub(crate) fn send(mut self, res: Result<T, Error>) {
+        if let Some(inner) = self.0.take() {
+            *inner.result.lock().unwrap() = Some(res.map_err(Arc::new));
+            inner.completed.notify_all();
+        }
+    }
+}
+
+impl<T> Drop for SharedSender<T> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.0.take() {
+            let err = if thread::panicking() { Error::JobPanicked } else { Error::Dropped };
+            *inner.result.lock().unwrap_or_else(|err| err.into_inner()) = Some(Err(Arc::new(err)));
+            inner.completed.notify_all();
         }
     }
 }
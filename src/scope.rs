@@ -6,7 +6,8 @@ use std::{
     cell::Cell,
     marker::PhantomData,
     thread::{self, Thread},
-    sync::{Arc, atomic::{AtomicUsize, Ordering}},
+    sync::{Arc, Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}},
+    time::Instant,
 };
 
 /// A scope within which jobs that refer to their parent scope may safely be spawned.
@@ -43,9 +44,76 @@ pub struct Scope<'pool, 'scope> {
 
 impl<'pool, 'scope> Scope<'pool, 'scope> {
     /// Enqueue a function that may refer to its parent scope to be executed as a job when a thread is free to do so.
+    ///
+    /// Scoped jobs are queued just like any other job, so on a pool with a single thread and [`Scheduling::Fifo`]
+    /// (the default), they start in the order that they were enqueued. This holds even when the scope is waited on by
+    /// the pool's only worker, which executes the pending jobs itself in queue order. Options that execute jobs on the
+    /// enqueueing thread instead ([`FullQueuePolicy::RunInline`] and [`ThreadPoolBuilder::with_inline_overflow`]) are
+    /// the exception, since an inline job starts before the jobs queued ahead of it.
+    ///
+    /// ```
+    /// use std::sync::Mutex;
+    ///
+    /// let pool = lagoon::ThreadPool::build().with_thread_count(1).finish().unwrap();
+    ///
+    /// let order = Mutex::new(Vec::new());
+    /// pool.scoped(|s| for i in 0..100 {
+    ///     let order = &order;
+    ///     s.run(move || order.lock().unwrap().push(i));
+    /// });
+    /// assert_eq!(*order.lock().unwrap(), (0..100).collect::<Vec<_>>());
+    ///
+    /// // The same goes for a nested scope waited on by the only worker
+    /// let order = Mutex::new(Vec::new());
+    /// pool.scoped(|s| s.run(|| pool.scoped(|s| for i in 0..100 {
+    ///     let order = &order;
+    ///     s.run(move || order.lock().unwrap().push(i));
+    /// })));
+    /// assert_eq!(*order.lock().unwrap(), (0..100).collect::<Vec<_>>());
+    /// ```
+    ///
+    /// A job that never runs, for example because it was evicted by [`FullQueuePolicy::DropOldest`], doesn't hold up
+    /// the scope.
+    ///
+    /// ```
+    /// use std::sync::{atomic::{AtomicUsize, Ordering}, Barrier};
+    /// use lagoon::FullQueuePolicy;
+    ///
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_thread_count(1)
+    ///     .with_queue_capacity(1)
+    ///     .with_full_queue_policy(FullQueuePolicy::DropOldest)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let (started, finished) = (Barrier::new(2), Barrier::new(2));
+    /// let ran = AtomicUsize::new(0);
+    /// pool.scoped(|s| {
+    ///     // Keep the only worker busy so that the queue fills up
+    ///     s.run(|| { started.wait(); finished.wait(); });
+    ///     started.wait();
+    ///     for _ in 0..3 {
+    ///         s.run(|| { ran.fetch_add(1, Ordering::Relaxed); });
+    ///     }
+    ///     finished.wait();
+    /// });
+    /// // The first two jobs were evicted to make space for the third
+    /// assert_eq!(ran.load(Ordering::Relaxed), 1);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This function panics in the same cases as [`ThreadPool::run`] (for example, if the pool has been closed with
+    /// [`ThreadPool::close`]). The job is dropped without running, so the scope doesn't wait for it while unwinding.
     pub fn run<F: FnOnce() + Send + 'scope>(&self, f: F) {
         let parent = self.parent.clone();
         parent.1.fetch_add(1, Ordering::Acquire);
+        // Created before the job is enqueued so that the count is decremented even if the job never runs (because it
+        // was evicted from a full queue, or couldn't be enqueued at all)
+        let guard = scopeguard::guard(parent, |parent| {
+            parent.1.fetch_sub(1, Ordering::Release);
+            parent.0.unpark();
+        });
 
         // Safety: we manually use `parent` to ensure that the calling scope lives long enough
         let f = unsafe { std::mem::transmute::<
@@ -53,32 +121,258 @@ impl<'pool, 'scope> Scope<'pool, 'scope> {
             Box<dyn FnOnce() + Send + 'static>,
         >(Box::new(f)) };
 
+        // The fields of a tuple are dropped in order, so a job that is dropped without running drops `f` before the
+        // guard allows the scope to end
+        let job = (f, guard);
         self.pool.run(move || {
-            let _guard = scopeguard::guard(parent, |parent| {
-                parent.1.fetch_sub(1, Ordering::Release);
-                parent.0.unpark();
-            });
+            let (f, _guard) = job;
             f();
         })
     }
 
+    /// Create a view of this scope in which each job is given a shared reference to the given context, avoiding the
+    /// need to capture it in every job. See [`ContextScope`] for more information.
+    pub fn with_context<'a, C: Sync>(&'a self, ctx: &'scope C) -> ContextScope<'a, 'pool, 'scope, C> {
+        ContextScope { scope: self, ctx }
+    }
+
+    /// Create a nested scope in which each job is given exclusive access to a local value belonging to the worker that
+    /// executes it, returning the result of the function along with every local value that was created once all of the
+    /// jobs in the nested scope have finished. See [`LocalScope`] for more information.
+    pub fn with_local<L, R>(&self, f: impl FnOnce(&LocalScope<'pool, 'scope, L>) -> R) -> (R, Vec<L>)
+    where
+        L: Default + Send + 'scope,
+    {
+        let locals = Arc::new(Locals {
+            slots: (0..self.pool.thread_count()).map(|_| Mutex::new(None)).collect(),
+            extra: Mutex::new(Vec::new()),
+        });
+
+        // Jobs in the nested scope may refer to anything that jobs in this scope may refer to, since this scope cannot
+        // end until the nested scope has
+        let r = run::<'pool, 'scope, _>(self.pool, |scope| f(&LocalScope { scope, locals: locals.clone() }));
+
+        let mut all = std::mem::take(&mut *locals.extra.lock().unwrap());
+        all.extend(locals.slots.iter().filter_map(|slot| slot.lock().unwrap().take()));
+        (r, all)
+    }
+
     /// Enqueue a function that may refer to its parent scope to be executed as a job when a thread is free to do so,
     /// returning a handle that allows retrieval of the return value of the function.
     #[cfg(feature = "recv")]
     #[cfg_attr(docsrs, doc(cfg(feature = "recv")))]
     pub fn run_recv<F: FnOnce() -> R + Send + 'scope, R: Send + 'scope>(&self, f: F) -> recv::JobHandle<R> {
-        let (tx, rx) = oneshot::channel();
-        self.run(move || { let _ = tx.send(f()); });
-        recv::JobHandle::new(rx)
+        let (tx, handle) = recv::JobHandle::new();
+        let shared = self.pool.shared.clone();
+        self.run(move || tx.run(|| unwind::catch(&shared, f).map_err(Error::Panicked)));
+        handle
+    }
+}
+
+/// A view of a [`Scope`] in which each job is given a shared reference to a context, such as read-only data that every
+/// job needs. It may be created with [`Scope::with_context`].
+///
+/// This is equivalent to capturing the reference in each job, but is less verbose and keeps the jobs small.
+///
+/// ```
+/// struct Config { scale: u32 }
+///
+/// let config = Config { scale: 3 };
+/// let mut data = (0..100).collect::<Vec<u32>>();
+///
+/// lagoon::ThreadPool::default().scoped(|s| {
+///     let s = s.with_context(&config);
+///     for x in data.iter_mut() {
+///         s.run(move |config| *x *= config.scale);
+///     }
+/// });
+///
+/// assert!(data.iter().enumerate().all(|(i, x)| *x == i as u32 * 3));
+/// ```
+pub struct ContextScope<'a, 'pool, 'scope, C> {
+    scope: &'a Scope<'pool, 'scope>,
+    ctx: &'scope C,
+}
+
+impl<'a, 'pool, 'scope, C: Sync> ContextScope<'a, 'pool, 'scope, C> {
+    /// Enqueue a function that may refer to its parent scope to be executed as a job when a thread is free to do so,
+    /// passing it the context of this scope.
+    pub fn run<F: FnOnce(&'scope C) + Send + 'scope>(&self, f: F) {
+        let ctx = self.ctx;
+        self.scope.run(move || f(ctx));
+    }
+
+    /// Returns the [`Scope`] that this view refers to, for spawning jobs that don't need the context.
+    pub fn scope(&self) -> &'a Scope<'pool, 'scope> { self.scope }
+}
+
+/// A scope within which jobs that refer to their parent scope may be spawned, with each job being given exclusive
+/// access to a local value belonging to the worker that executes it. It may be created with [`Scope::with_local`].
+///
+/// Each worker creates its local value with [`Default`] the first time that it executes a job in the scope, so jobs
+/// never contend over a shared value. This is useful for parallel reductions, with one accumulator per worker being
+/// merged at the end.
+///
+/// ```
+/// let data = (0..1000).collect::<Vec<u64>>();
+///
+/// let pool = lagoon::ThreadPool::default();
+/// let ((), sums) = pool.scoped(|s| s.with_local(|s| {
+///     for chunk in data.chunks(10) {
+///         s.run(move |sum: &mut u64| *sum += chunk.iter().sum::<u64>());
+///     }
+/// }));
+///
+/// assert_eq!(sums.into_iter().sum::<u64>(), data.iter().sum());
+/// ```
+pub struct LocalScope<'pool, 'scope, L> {
+    scope: Scope<'pool, 'scope>,
+    locals: Arc<Locals<L>>,
+}
+
+// The local values of a scope, indexed by worker
+struct Locals<L> {
+    // The lock for a slot is only taken by its own worker, so it is uncontended
+    slots: Vec<Mutex<Option<L>>>,
+    // Values that could not be returned to their slot because the worker began using another in the meantime (i.e:
+    // while running a job from the same scope that was nested within the job that owned the original)
+    extra: Mutex<Vec<L>>,
+}
+
+impl<'pool, 'scope, L: Default + Send + 'scope> LocalScope<'pool, 'scope, L> {
+    /// Enqueue a function that may refer to its parent scope to be executed as a job when a thread is free to do so,
+    /// passing it the local value of the worker that executes it.
+    ///
+    /// If the function panics, the local value that it was given is discarded.
+    pub fn run<F: FnOnce(&mut L) + Send + 'scope>(&self, f: F) {
+        let locals = self.locals.clone();
+        self.scope.run(move || {
+            let slot = current_worker_index().and_then(|idx| locals.slots.get(idx));
+            // Take the value out of the slot rather than holding the lock so that nested jobs can't deadlock
+            let mut local = slot.and_then(|slot| slot.lock().unwrap().take()).unwrap_or_default();
+            f(&mut local);
+
+            let mut local = Some(local);
+            if let Some(slot) = slot {
+                let mut slot = slot.lock().unwrap();
+                if slot.is_none() {
+                    *slot = local.take();
+                }
+            }
+            locals.extra.lock().unwrap().extend(local);
+        });
+    }
+}
+
+/// A scope within which jobs that refer to their parent scope may be spawned, with the results of each job being
+/// collected automatically. It may be created with [`ThreadPool::scoped_collect`].
+///
+/// Results are returned in the order that their jobs were spawned, regardless of the order in which they finished.
+///
+/// ```
+/// let data = (0..100).collect::<Vec<u32>>();
+///
+/// let squares = lagoon::ThreadPool::default().scoped_collect(|s| {
+///     for x in data.iter() {
+///         s.run(move || *x * *x);
+///     }
+/// });
+///
+/// assert!(data
+///     .iter()
+///     .map(|x| x * x)
+///     .eq(squares.into_iter()));
+/// ```
+pub struct CollectScope<'pool, 'scope, R> {
+    scope: Scope<'pool, 'scope>,
+    results: Arc<Mutex<Vec<Option<R>>>>,
+}
+
+impl<'pool, 'scope, R: Send + 'scope> CollectScope<'pool, 'scope, R> {
+    /// Enqueue a function that may refer to its parent scope to be executed as a job when a thread is free to do so,
+    /// collecting its return value into the results of the scope.
+    pub fn run<F: FnOnce() -> R + Send + 'scope>(&self, f: F) {
+        let idx = {
+            let mut results = self.results.lock().unwrap();
+            results.push(None);
+            results.len() - 1
+        };
+        let results = self.results.clone();
+        self.scope.run(move || {
+            let x = f();
+            results.lock().unwrap()[idx] = Some(x);
+        });
+    }
+}
+
+/// A view of a [`Scope`] in which each job may fail, created with [`ThreadPool::scoped_try`].
+///
+/// Once a job has failed, the scope stops running jobs: jobs enqueued afterwards are discarded, and jobs that are still
+/// queued are skipped when a worker gets to them. Jobs that are already running can't be stopped, so the scope still
+/// waits for them to finish. The first error to occur is returned by [`ThreadPool::scoped_try`].
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// let pool = lagoon::ThreadPool::build().with_thread_count(1).finish().unwrap();
+/// let data = (0..100).collect::<Vec<u32>>();
+/// let checked = AtomicUsize::new(0);
+///
+/// let res = pool.scoped_try(|s| {
+///     for x in data.iter() {
+///         let checked = &checked;
+///         s.run(move || {
+///             checked.fetch_add(1, Ordering::Relaxed);
+///             if *x == 10 { Err(format!("{} is invalid", x)) } else { Ok(()) }
+///         });
+///     }
+/// });
+///
+/// assert_eq!(res, Err("10 is invalid".to_string()));
+/// // Validation stopped at the first invalid item
+/// assert!(checked.load(Ordering::Relaxed) < data.len());
+/// ```
+pub struct TryScope<'pool, 'scope, E> {
+    scope: Scope<'pool, 'scope>,
+    error: Arc<Mutex<Option<E>>>,
+    failed: Arc<AtomicBool>,
+}
+
+impl<'pool, 'scope, E: Send + 'scope> TryScope<'pool, 'scope, E> {
+    /// Enqueue a function that may refer to its parent scope to be executed as a job when a thread is free to do so,
+    /// unless a job in the scope has already failed.
+    pub fn run<F: FnOnce() -> Result<(), E> + Send + 'scope>(&self, f: F) {
+        if self.has_failed() {
+            return;
+        }
+        let (error, failed) = (self.error.clone(), self.failed.clone());
+        self.scope.run(move || {
+            if failed.load(Ordering::Acquire) {
+                return;
+            }
+            if let Err(err) = f() {
+                // Only the first error is kept
+                error.lock().unwrap().get_or_insert(err);
+                failed.store(true, Ordering::Release);
+            }
+        });
     }
+
+    /// Returns whether a job in the scope has failed. This may be used to stop producing work early.
+    pub fn has_failed(&self) -> bool { self.failed.load(Ordering::Acquire) }
 }
 
 pub(crate) fn run<'pool, 'scope, R>(pool: &'pool ThreadPool, f: impl FnOnce(Scope<'pool, 'scope>) -> R) -> R {
     let this = Arc::new((thread::current(), AtomicUsize::new(0)));
 
     let _guard = scopeguard::guard(this.clone(), |this| {
+        // If we're a worker of this pool, help out with queued jobs instead of sitting idle. This also avoids deadlock
+        // when every worker is waiting on a nested scope.
+        let is_worker = pool.is_current_worker();
         while this.1.load(Ordering::SeqCst) > 0 {
-            thread::park();
+            if !(is_worker && pool.run_one_pending()) {
+                thread::park();
+            }
         }
     });
 
@@ -88,3 +382,65 @@ pub(crate) fn run<'pool, 'scope, R>(pool: &'pool ThreadPool, f: impl FnOnce(Scop
         phantom: PhantomData,
     })
 }
+
+pub(crate) fn run_timeout<'pool, R>(
+    pool: &'pool ThreadPool,
+    timeout: Duration,
+    f: impl FnOnce(Scope<'pool, 'static>) -> R,
+) -> Result<R, Error> {
+    let this = Arc::new((thread::current(), AtomicUsize::new(0)));
+
+    // Jobs can only refer to `'static` data, so it's fine for them to outlive the scope. That means that there's no
+    // need to wait for them if `f` panics, or once the timeout has elapsed.
+    let r = f(Scope {
+        pool,
+        parent: this.clone(),
+        phantom: PhantomData,
+    });
+
+    let deadline = Instant::now().checked_add(timeout);
+    let is_worker = pool.is_current_worker();
+    while this.1.load(Ordering::SeqCst) > 0 {
+        if is_worker && pool.run_one_pending() {
+            continue;
+        }
+        match deadline.map(|deadline| deadline.checked_duration_since(Instant::now())) {
+            None => thread::park(),
+            Some(Some(remaining)) if remaining > Duration::ZERO => thread::park_timeout(remaining),
+            Some(_) => return Err(Error::Timeout),
+        }
+    }
+    Ok(r)
+}
+
+pub(crate) fn run_try<'pool, 'scope, E: Send + 'scope>(
+    pool: &'pool ThreadPool,
+    f: impl FnOnce(&TryScope<'pool, 'scope, E>),
+) -> Result<(), E> {
+    let error = Arc::new(Mutex::new(None));
+
+    run(pool, |scope| f(&TryScope { scope, error: error.clone(), failed: Arc::new(AtomicBool::new(false)) }));
+
+    // All jobs have finished by this point
+    let err = error.lock().unwrap().take();
+    match err {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn run_collect<'pool, 'scope, R: Send + 'scope>(
+    pool: &'pool ThreadPool,
+    f: impl FnOnce(&CollectScope<'pool, 'scope, R>),
+) -> Vec<R> {
+    let results = Arc::new(Mutex::new(Vec::new()));
+
+    run(pool, |scope| f(&CollectScope { scope, results: results.clone() }));
+
+    // All jobs have finished by this point, so every slot is filled unless its job panicked
+    let results = std::mem::take(&mut *results.lock().unwrap());
+    results
+        .into_iter()
+        .map(|x| x.expect("a job spawned in the scope panicked"))
+        .collect()
+}
@@ -0,0 +1,108 @@
+use super::*;
+
+use std::{
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+};
+
+type StrandJob = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct StrandQueue {
+    jobs: VecDeque<StrandJob>,
+    // Whether a job is currently draining the queue on one of the pool's workers
+    running: bool,
+}
+
+/// A handle that executes jobs on a [`ThreadPool`] one at a time, in the order that they are enqueued. It may be
+/// created with [`ThreadPool::strand`].
+///
+/// Jobs enqueued on the same strand never run concurrently with one another, so they may share mutable state without
+/// contending on locks. Jobs on different strands (and other jobs on the pool) run in parallel as usual. Unlike a
+/// [`PinnedWorker`], a strand doesn't occupy a worker while it has no jobs: jobs enqueued while the strand is idle are
+/// executed by whichever worker is free, and jobs enqueued while it is busy are executed by the same worker, after
+/// the jobs before them.
+///
+/// Cloning a strand gives another handle to the same strand. Panics are handled according to the pool's
+/// [`PanicPolicy`], and a panicking job doesn't prevent the jobs after it from running.
+///
+/// ```
+/// use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
+///
+/// let pool = lagoon::ThreadPool::default();
+/// let strand = pool.strand();
+///
+/// let running = Arc::new(AtomicBool::new(false));
+/// let order = Arc::new(Mutex::new(Vec::new()));
+/// for i in 0..100 {
+///     let (running, order) = (running.clone(), order.clone());
+///     strand.run(move || {
+///         assert!(!running.swap(true, Ordering::SeqCst), "strand jobs overlapped");
+///         order.lock().unwrap().push(i);
+///         running.store(false, Ordering::SeqCst);
+///     });
+/// }
+/// pool.wait_idle();
+///
+/// assert_eq!(*order.lock().unwrap(), (0..100).collect::<Vec<_>>());
+/// ```
+#[derive(Clone)]
+pub struct Strand<'pool> {
+    pool: &'pool ThreadPool,
+    queue: Arc<Mutex<StrandQueue>>,
+}
+
+impl<'pool> Strand<'pool> {
+    pub(crate) fn new(pool: &'pool ThreadPool) -> Self {
+        Self { pool, queue: Arc::default() }
+    }
+
+    /// Enqueue a function to be executed as a job on the strand, after all previously enqueued jobs on the strand have
+    /// finished.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic under the same circumstances as [`ThreadPool::run`].
+    pub fn run<F: FnOnce() + Send + 'static>(&self, f: F) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.jobs.push_back(Box::new(f));
+        if !queue.running {
+            queue.running = true;
+            drop(queue);
+            let (shared, queue) = (self.pool.shared.clone(), self.queue.clone());
+            self.pool.run(move || drain(&shared, &queue));
+        }
+    }
+}
+
+// Execute the jobs in the queue one after another until it is empty
+fn drain(shared: &Shared, queue: &Mutex<StrandQueue>) {
+    // If a job's panic isn't caught, let the next job to be enqueued start draining the queue again
+    struct Stop<'a>(&'a Mutex<StrandQueue>);
+    impl Drop for Stop<'_> {
+        fn drop(&mut self) {
+            if thread::panicking() {
+                self.0.lock().unwrap_or_else(|err| err.into_inner()).running = false;
+            }
+        }
+    }
+
+    let _stop = Stop(queue);
+    loop {
+        let f = {
+            let mut queue = queue.lock().unwrap();
+            match queue.jobs.pop_front() {
+                Some(f) => f,
+                None => {
+                    queue.running = false;
+                    return;
+                },
+            }
+        };
+        if !shared.config.catch_panics() {
+            f();
+        } else if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+            unwind::handle(shared, &*payload);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use super::*;
+
+use std::{any::Any, panic};
+#[cfg(feature = "backtrace")]
+use std::cell::{Cell, RefCell};
+
+/// What a [`ThreadPool`] should do when one of its jobs panics, configured with
+/// [`ThreadPoolBuilder::with_panic_policy`].
+///
+/// In every case, the panic is first reported by the panic hook as usual. The common cases may also be configured with
+/// a [`PanicStrategy`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PanicPolicy {
+    /// Catch the panic and keep the worker running. Joining a handle to the job returns [`Error::JobPanicked`]. This is
+    /// the default.
+    #[default]
+    Catch,
+    /// Abort the process, as if the job had been run with `panic = "abort"`.
+    Abort,
+    /// Catch the panic and keep the worker running, storing the panic payload so that joining a handle to the job
+    /// returns [`Error::Panicked`]. Jobs without handles behave as with [`PanicPolicy::Catch`].
+    Propagate,
+}
+
+/// What a [`ThreadPool`] should do when one of its jobs panics, configured with
+/// [`ThreadPoolBuilder::with_panic_strategy`].
+///
+/// This is a simpler view of [`PanicPolicy`], which every strategy converts into. Whatever the strategy, panics are
+/// counted by [`ThreadPool::panic_count`] and passed to the handler given to [`ThreadPoolBuilder::on_panic`], if any.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PanicStrategy {
+    /// Catch the panic and keep the worker running, as with [`PanicPolicy::Catch`]. This is the default.
+    #[default]
+    Catch,
+    /// Abort the process once the panic has been reported, as with [`PanicPolicy::Abort`].
+    Abort,
+    /// Catch the panic and keep the worker running, counting it and passing its payload to the panic handler. Since
+    /// panics are counted and handled under every strategy, this behaves exactly like [`PanicStrategy::Catch`], but
+    /// says so explicitly.
+    CatchAndCount,
+}
+
+impl From<PanicStrategy> for PanicPolicy {
+    fn from(strategy: PanicStrategy) -> Self {
+        match strategy {
+            PanicStrategy::Catch | PanicStrategy::CatchAndCount => Self::Catch,
+            PanicStrategy::Abort => Self::Abort,
+        }
+    }
+}
+
+/// The payload of a panic that occurred in a job, returned by [`Error::Panicked`].
+pub struct Panic {
+    // Payloads are only `Send`, but the mutex makes this type (and hence `Error`) `Sync` too
+    payload: Mutex<Box<dyn Any + Send>>,
+    #[cfg(feature = "backtrace")]
+    backtrace: Option<String>,
+}
+
+impl Panic {
+    pub(crate) fn new(payload: Box<dyn Any + Send>) -> Self {
+        Self {
+            payload: Mutex::new(payload),
+            #[cfg(feature = "backtrace")]
+            backtrace: None,
+        }
+    }
+
+    /// Returns the backtrace of the panic, if one was captured.
+    ///
+    /// Backtraces are captured for panics in jobs with handles when the pool uses [`PanicPolicy::Propagate`]. This
+    /// works by installing a panic hook (which defers to the previous hook) the first time such a job is executed, so
+    /// no backtrace is captured if the hook is later replaced with [`std::panic::set_hook`]. Backtraces are captured
+    /// regardless of the `RUST_BACKTRACE` environment variable.
+    ///
+    /// ```
+    /// let pool = lagoon::ThreadPool::build()
+    ///     .with_panic_policy(lagoon::PanicPolicy::Propagate)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// match pool.run_recv(|| -> u32 { panic!("oh no") }).join() {
+    ///     Err(lagoon::Error::Panicked(panic)) => println!("{}\n{}", panic, panic.backtrace().unwrap()),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    #[cfg(feature = "backtrace")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "backtrace")))]
+    pub fn backtrace(&self) -> Option<&str> { self.backtrace.as_deref() }
+
+    /// Returns the panic message, if the payload is a string (as it is for panics created with [`panic!`]).
+    pub fn message(&self) -> Option<String> {
+        let payload = self.payload.lock().unwrap_or_else(|err| err.into_inner());
+        payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+    }
+
+    /// Returns the payload of the panic.
+    pub fn into_payload(self) -> Box<dyn Any + Send> {
+        self.payload.into_inner().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Resume the panic on the current thread.
+    pub fn resume(self) -> ! {
+        panic::resume_unwind(self.into_payload())
+    }
+}
+
+impl fmt::Debug for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("Panic");
+        s.field("message", &self.message());
+        #[cfg(feature = "backtrace")]
+        s.field("backtrace", &self.backtrace);
+        s.finish()
+    }
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.message() {
+            Some(msg) => write!(f, "{}", msg),
+            None => write!(f, "Box<dyn Any>"),
+        }
+    }
+}
+
+// Run a function that produces the result of a job with a handle, catching the panic if the policy requires its
+// payload to be propagated to the handle
+#[cfg(feature = "recv")]
+pub(crate) fn catch<R>(shared: &Shared, f: impl FnOnce() -> R) -> Result<R, Panic> {
+    match shared.config.panic_policy {
+        PanicPolicy::Propagate => {
+            #[cfg(feature = "backtrace")]
+            let _capture = Capture::start();
+            panic::catch_unwind(panic::AssertUnwindSafe(f)).map_err(|payload| {
+                report(shared, &*payload);
+                Panic {
+                    #[cfg(feature = "backtrace")]
+                    backtrace: BACKTRACE.with(|bt| bt.borrow_mut().take()),
+                    ..Panic::new(payload)
+                }
+            })
+        },
+        PanicPolicy::Catch | PanicPolicy::Abort => Ok(f()),
+    }
+}
+
+#[cfg(feature = "backtrace")]
+thread_local! {
+    // Whether panics on the current thread will be caught by `catch`, and the backtrace of the last such panic
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+    static BACKTRACE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// Captures the backtraces of panics on the current thread while it exists. By the time a panic has been caught, the
+// stack has already been unwound, so the backtrace must be captured by the panic hook.
+#[cfg(feature = "backtrace")]
+struct Capture(bool);
+
+#[cfg(feature = "backtrace")]
+impl Capture {
+    fn start() -> Self {
+        static HOOK: std::sync::Once = std::sync::Once::new();
+        HOOK.call_once(|| {
+            let prev = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                if CAPTURING.with(Cell::get) {
+                    let bt = std::backtrace::Backtrace::force_capture().to_string();
+                    BACKTRACE.with(|b| *b.borrow_mut() = Some(bt));
+                }
+                prev(info);
+            }));
+        });
+        // Jobs may execute other jobs, so remember whether we were already capturing
+        Self(CAPTURING.with(|c| c.replace(true)))
+    }
+}
+
+#[cfg(feature = "backtrace")]
+impl Drop for Capture {
+    fn drop(&mut self) { CAPTURING.with(|c| c.set(self.0)); }
+}
+
+// Count a panic that occurred in a job and pass it to the panic handler, if any
+pub(crate) fn report(shared: &Shared, payload: &(dyn Any + Send)) {
+    shared.panicked.fetch_add(1, Ordering::Relaxed);
+    if let Some(on_panic) = &shared.config.on_panic {
+        // A panicking handler has no caller to propagate to, so there's nothing to do but let the panic hook report it
+        let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| on_panic(payload)));
+    }
+}
+
+// Handle a panic that occurred in a job according to the panic policy
+pub(crate) fn handle(shared: &Shared, payload: &(dyn Any + Send)) {
+    report(shared, payload);
+    if shared.config.panic_policy == PanicPolicy::Abort {
+        std::process::abort();
+    }
+}
@@ -0,0 +1,477 @@
+use super::*;
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    panic::{self, AssertUnwindSafe},
+    time::Instant,
+};
+use channel::{RecvTimeoutError, TryRecvError};
+
+/// What the workers of a [`ThreadPool`] do when there are no jobs in the queue, configured with
+/// [`ThreadPoolBuilder::with_idle_strategy`].
+///
+/// Blocking a worker is cheap while it waits, but waking it up again when a job is enqueued takes time (often several
+/// microseconds), which can dominate the latency of small jobs. Spinning before blocking lets a worker pick up jobs
+/// that arrive shortly after the queue empties without being woken up, at the cost of burning CPU time while it
+/// spins.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IdleStrategy {
+    /// Block until a job is enqueued. This is the default, and is the most efficient choice when latency is not
+    /// critical (for example, on battery-powered devices).
+    #[default]
+    Park,
+    /// Check the queue the given number of times, with a spin-loop hint between each check, before blocking.
+    SpinThenPark {
+        /// The number of times to check the queue while spinning.
+        spins: usize,
+    },
+    /// Check the queue the given number of times with a spin-loop hint between each check, then the given number of
+    /// times yielding to the OS scheduler between each check, before blocking. Yielding allows other threads to run on
+    /// the same core while waiting, so is more polite than spinning for longer.
+    SpinThenYieldThenPark {
+        /// The number of times to check the queue while spinning.
+        spins: usize,
+        /// The number of times to check the queue while yielding.
+        yields: usize,
+    },
+}
+
+impl IdleStrategy {
+    // Check the queue repeatedly according to the strategy, without blocking. Returns `None` if the queue stayed empty
+    // and the worker should block.
+    fn poll(self, rx: &queue::QueueReceiver) -> Option<Result<Job, RecvTimeoutError>> {
+        let (spins, yields) = match self {
+            Self::Park => return None,
+            Self::SpinThenPark { spins } => (spins, 0),
+            Self::SpinThenYieldThenPark { spins, yields } => (spins, yields),
+        };
+        for i in 0..spins.saturating_add(yields) {
+            match rx.try_recv() {
+                Ok(job) => return Some(Ok(job)),
+                Err(TryRecvError::Disconnected) => return Some(Err(RecvTimeoutError::Disconnected)),
+                Err(TryRecvError::Empty) if i < spins => std::hint::spin_loop(),
+                Err(TryRecvError::Empty) => std::thread::yield_now(),
+            }
+        }
+        None
+    }
+}
+
+thread_local! {
+    // The pool identifier and index of the worker running on the current thread, if any. Temporary workers have no
+    // index.
+    static CURRENT: Cell<Option<(usize, Option<usize>)>> = const { Cell::new(None) };
+    // The state of the worker running on the current thread, if the pool was configured with one
+    static STATE: RefCell<Option<Box<dyn Any>>> = const { RefCell::new(None) };
+    // The address of the start of the stack of the worker running on the current thread and the size of the stack, if
+    // the pool was configured with one
+    static STACK: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+    // The job running on the current thread, if any
+    static JOB: RefCell<Option<JobInfo>> = const { RefCell::new(None) };
+    // The random number generator of the worker running on the current thread, if the pool was configured with a seed
+    #[cfg(feature = "rand")]
+    static RNG: RefCell<Option<rand::rngs::SmallRng>> = const { RefCell::new(None) };
+}
+
+// Returns the pool identifier and index of the worker running on the current thread, if any
+pub(crate) fn current() -> Option<(usize, Option<usize>)> { CURRENT.with(|current| current.get()) }
+
+pub(crate) fn current_job() -> Option<JobInfo> { JOB.with(|job| job.borrow().clone()) }
+
+pub(crate) fn remaining_stack() -> Option<usize> {
+    let (start, size) = STACK.with(Cell::get)?;
+    // Stacks grow downwards on every platform that we support
+    Some(size.saturating_sub(start.saturating_sub(stack_address())))
+}
+
+// Returns the address of a local variable, which is an approximation of how far the current thread's stack extends
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(std::ptr::addr_of!(marker)) as usize
+}
+
+/// A handle to the random number generator of the worker running on the current thread. It may be obtained with
+/// [`worker_rng`](crate::worker_rng).
+///
+/// The handle refers to the generator of the thread that obtained it, so it can't be sent to other threads.
+#[cfg(feature = "rand")]
+#[derive(Debug)]
+pub struct WorkerRng {
+    // Not `Send`, since the generator belongs to the current thread
+    phantom: std::marker::PhantomData<*const ()>,
+}
+
+#[cfg(feature = "rand")]
+pub(crate) fn rng() -> Option<WorkerRng> {
+    RNG.with(|rng| rng.borrow().is_some()).then_some(WorkerRng { phantom: std::marker::PhantomData })
+}
+
+#[cfg(feature = "rand")]
+impl WorkerRng {
+    fn with<R>(&mut self, f: impl FnOnce(&mut rand::rngs::SmallRng) -> R) -> R {
+        RNG.with(|rng| f(rng.borrow_mut().as_mut().expect("worker random number generator is gone")))
+    }
+}
+
+#[cfg(feature = "rand")]
+impl rand::RngCore for WorkerRng {
+    fn next_u32(&mut self) -> u32 { self.with(|rng| rng.next_u32()) }
+
+    fn next_u64(&mut self) -> u64 { self.with(|rng| rng.next_u64()) }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) { self.with(|rng| rng.fill_bytes(dest)) }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.with(|rng| rng.try_fill_bytes(dest))
+    }
+}
+
+// Spawn the workers of the pool that have not yet been spawned, until the given number of workers have been
+pub(crate) fn spawn_workers(shared: &Arc<Shared>, count: usize) -> Result<(), Error> {
+    let _lock = shared.spawn_lock.lock().unwrap();
+    let (first, end) = (shared.spawned.load(Ordering::Acquire), count.min(shared.thread_count));
+    for idx in first..end {
+        shared.add_handles(Some(spawn(shared, idx).map_err(|error| Error::Spawn { spawned: idx, error })?));
+        shared.spawned.store(idx + 1, Ordering::Release);
+    }
+
+    // Wait for each new worker to warm up
+    if shared.config.warms_up() {
+        let mut warmed_up = shared.warmed_up.lock().unwrap();
+        while warmed_up[first..end].iter().any(|warm| !warm) {
+            warmed_up = shared.warmed_up_reported.wait(warmed_up).unwrap();
+        }
+    }
+
+    // Wait for each new worker to report whether it managed to apply the realtime policy
+    #[cfg(all(unix, feature = "realtime"))]
+    if shared.config.realtime_policy.is_some() {
+        let mut results = shared.realtime_results.lock().unwrap();
+        while results[first..end].iter().any(|res| res.is_none()) {
+            results = shared.realtime_reported.wait(results).unwrap();
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn spawn(shared: &Arc<Shared>, idx: usize) -> io::Result<JoinHandle<()>> {
+    let shared2 = shared.clone();
+    spawn_thread(shared, idx, false, move || run(&shared2, idx))
+}
+
+// Wakes the temporary workers spawned by `ThreadPool::scoped_with_threads`. Temporary workers can't block on the
+// queue itself, since they would then have no way to notice that they've been retired, so they wait on this instead,
+// which is notified whenever a job is enqueued while they're running and when they're retired.
+#[cfg(feature = "scope")]
+#[derive(Default)]
+pub(crate) struct Temporaries {
+    // The number of temporary workers that are running
+    running: AtomicUsize,
+    // Incremented each time the temporary workers are notified
+    generation: Mutex<u64>,
+    notified: std::sync::Condvar,
+}
+
+#[cfg(feature = "scope")]
+impl Temporaries {
+    // Wake a temporary worker, if any are running, because a job has been enqueued
+    pub(crate) fn notify_job(&self) {
+        if self.running.load(Ordering::Acquire) > 0 {
+            *self.generation.lock().unwrap() += 1;
+            self.notified.notify_one();
+        }
+    }
+
+    // Wake every temporary worker so that the retired ones notice
+    pub(crate) fn notify_retired(&self) {
+        *self.generation.lock().unwrap() += 1;
+        self.notified.notify_all();
+    }
+}
+
+// Spawn a temporary worker that executes jobs from the queue alongside the permanent workers until told to retire.
+// Temporary workers only execute jobs: they don't run the pool's hooks, or have worker state.
+#[cfg(feature = "scope")]
+pub(crate) fn spawn_temporary(shared: &Arc<Shared>, idx: usize, retire: Arc<AtomicBool>) -> io::Result<JoinHandle<()>> {
+    let temporaries = &shared.temporaries;
+    // Count the worker as running before it's spawned, so that no job enqueued once it's waiting goes unnoticed
+    temporaries.running.fetch_add(1, Ordering::AcqRel);
+    let shared2 = shared.clone();
+    let res = spawn_thread(shared, idx, true, move || {
+        // Temporary workers belong to the pool (so they help out when waiting on a nested scope), but have no index
+        CURRENT.with(|current| current.set(Some((Arc::as_ptr(&shared2) as usize, None))));
+        let temporaries = &shared2.temporaries;
+        loop {
+            // Note the generation before checking the queue, so that a job enqueued after the check wakes us up
+            let generation = *temporaries.generation.lock().unwrap();
+            match shared2.rx.try_recv() {
+                Ok(job) => {
+                    shared2.pause.wait_while_paused();
+                    execute(&shared2, job);
+                    continue;
+                },
+                Err(TryRecvError::Disconnected) => break,
+                Err(TryRecvError::Empty) => {},
+            }
+            let lock = temporaries.generation.lock().unwrap();
+            let _lock = temporaries
+                .notified
+                .wait_while(lock, |current| *current == generation && !retire.load(Ordering::Acquire))
+                .unwrap();
+            if retire.load(Ordering::Acquire) {
+                break;
+            }
+        }
+        temporaries.running.fetch_sub(1, Ordering::AcqRel);
+    });
+    if res.is_err() {
+        temporaries.running.fetch_sub(1, Ordering::AcqRel);
+    }
+    res
+}
+
+// Spawn a thread for the worker with the given index, using the pool's spawner if it has one
+fn spawn_thread(
+    shared: &Shared,
+    idx: usize,
+    temporary: bool,
+    f: impl FnOnce() + Send + 'static,
+) -> io::Result<JoinHandle<()>> {
+    let config = &shared.config;
+    let thread_config = ThreadConfig {
+        index: idx,
+        name: config.thread_name.clone().or_else(|| config.pool_name.as_ref().map(|pool| format!("{}-{}", pool, idx))),
+        stack_size: config.thread_stack_size,
+        temporary,
+    };
+    match &config.spawner {
+        Some(spawner) => spawner(thread_config, Box::new(f)),
+        None => thread_config.builder().spawn(f),
+    }
+}
+
+fn run(shared: &Arc<Shared>, idx: usize) {
+    let config = &shared.config;
+    CURRENT.with(|current| current.set(Some((Arc::as_ptr(shared) as usize, Some(idx)))));
+    // Very little of the stack has been used by the time we get here, so this frame is a good estimate of its start
+    if let Some(size) = config.thread_stack_size {
+        STACK.with(|stack| stack.set(Some((stack_address(), size))));
+    }
+    setup(shared, idx);
+
+    if let Some(init) = &config.worker_state {
+        // If the state can't be created, jobs that need it will panic instead
+        if let Ok(state) = panic::catch_unwind(AssertUnwindSafe(|| init(idx))) {
+            STATE.with(|s| *s.borrow_mut() = Some(state));
+        }
+    }
+    #[cfg(feature = "rand")]
+    if let Some(base) = config.rng_seed {
+        use rand::SeedableRng;
+        let rng = rand::rngs::SmallRng::seed_from_u64(base.wrapping_add(idx as u64));
+        RNG.with(|r| *r.borrow_mut() = Some(rng));
+    }
+
+    if let Some(bytes) = config.prefault_stack {
+        prefault_stack(bytes);
+    }
+    if let Some(warmup) = &config.warmup {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| warmup()));
+    }
+    run_hook(&config.after_start, idx);
+    if config.warms_up() {
+        shared.warmed_up.lock().unwrap()[idx] = true;
+        shared.warmed_up_reported.notify_all();
+    }
+
+    let mut jobs_until_recycle = config.recycle_after_jobs;
+    let mut recycle_at = config.recycle_after_age.map(|age| Instant::now() + age);
+    loop {
+        // A worker only ever decrements the count after incrementing it, so the count can't underflow
+        shared.idle.fetch_add(1, Ordering::Release);
+        let res = config.idle_strategy.poll(&shared.rx).unwrap_or_else(|| shared.rx.recv_deadline(recycle_at));
+        shared.idle.fetch_sub(1, Ordering::Release);
+        match res {
+            Ok(job) => {
+                shared.pause.wait_while_paused();
+                // Counted before executing, so that the count is up to date by the time the job is seen to finish
+                #[cfg(feature = "metrics")]
+                shared.jobs_executed[idx].fetch_add(1, Ordering::Relaxed);
+                execute(shared, job);
+                jobs_until_recycle = jobs_until_recycle.map(|n| n.saturating_sub(1));
+                if jobs_until_recycle != Some(0) {
+                    continue;
+                }
+            },
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        // We're due to be recycled, so spawn a replacement before exiting
+        match spawn(shared, idx) {
+            Ok(handle) => {
+                shared.add_handles(Some(handle));
+                shared.threads_recycled.fetch_add(1, Ordering::Relaxed);
+                break;
+            },
+            Err(error) => {
+                config.warn(Warning::Recycle { worker: idx, error });
+                jobs_until_recycle = config.recycle_after_jobs;
+                recycle_at = config.recycle_after_age.map(|age| Instant::now() + age);
+            },
+        }
+    }
+    run_hook(&config.before_stop, idx);
+    STATE.with(|s| s.borrow_mut().take());
+    STACK.with(|stack| stack.set(None));
+    #[cfg(feature = "rand")]
+    RNG.with(|rng| rng.borrow_mut().take());
+}
+
+// Call a function with the state of the worker running on the current thread
+pub(crate) fn with_state<S: 'static, R>(f: impl FnOnce(&mut S) -> R) -> R {
+    // Puts the state back once the job is finished with it, even if the job panicked
+    struct Restore(Option<Box<dyn Any>>);
+    impl Drop for Restore {
+        fn drop(&mut self) { STATE.with(|s| *s.borrow_mut() = self.0.take()); }
+    }
+
+    // The state is taken out of the thread-local, rather than borrowed, so that jobs may execute other jobs (i.e:
+    // while waiting on a scope) that don't need the state
+    let mut state = Restore(STATE.with(|s| s.borrow_mut().take()));
+    let state = state.0
+        .as_mut()
+        .expect("worker state is unavailable: the job is not running on a worker with state, or it is already in use")
+        .downcast_mut::<S>()
+        .expect("worker state does not have the expected type");
+    f(state)
+}
+
+// Execute a job on the current thread, handling any panic that occurs according to the panic policy
+pub(crate) fn execute(shared: &Shared, job: Job) {
+    // Keeps the job counts and the current job up to date, even if the job panics and the panic is not caught. Jobs
+    // may execute other jobs (i.e: while waiting on a scope), so the previous job is restored afterwards.
+    struct Active<'a>(&'a Shared, Option<JobInfo>);
+    impl Drop for Active<'_> {
+        fn drop(&mut self) {
+            JOB.with(|job| *job.borrow_mut() = self.1.take());
+            self.0.active.fetch_sub(1, Ordering::AcqRel);
+            self.0.finish_job();
+        }
+    }
+
+    let config = &shared.config;
+    let info = JobInfo {
+        worker: current().filter(|(id, _)| *id == shared as *const Shared as usize).and_then(|(_, idx)| idx),
+        id: job.id,
+        name: job.name,
+    };
+    shared.active.fetch_add(1, Ordering::AcqRel);
+    let _active = Active(shared, JOB.with(|job| job.replace(Some(info.clone()))));
+    if let Some(on_job_start) = &config.on_job_start {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| on_job_start(info.clone())));
+    }
+    let start = config.on_job_end.as_ref().map(|_| Instant::now());
+
+    let outcome = if !config.catch_panics() {
+        (job.f)();
+        JobOutcome::Completed
+    } else {
+        let f = AssertUnwindSafe(job.f);
+        match panic::catch_unwind(move || (f.0)()) {
+            Ok(()) => JobOutcome::Completed,
+            Err(payload) => {
+                unwind::handle(shared, &*payload);
+                JobOutcome::Panicked
+            },
+        }
+    };
+
+    if let (Some(on_job_end), Some(start)) = (&config.on_job_end, start) {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| on_job_end(info, start.elapsed(), outcome)));
+    }
+}
+
+// Apply OS-level configuration to the current worker thread
+#[allow(unused_variables)]
+fn setup(shared: &Shared, idx: usize) {
+    let config = &shared.config;
+
+    #[cfg(feature = "affinity")]
+    if !shared.core_affinity.is_empty() {
+        let core = shared.core_affinity[idx % shared.core_affinity.len()];
+        if !core_affinity::set_for_current(core_affinity::CoreId { id: core }) {
+            config.warn(Warning::CoreAffinity { worker: idx, core });
+        }
+    }
+
+    // On Apple platforms, QoS classes take precedence over generic priorities
+    #[cfg(feature = "qos")]
+    let has_qos = config.qos_class.is_some() && cfg!(any(target_os = "macos", target_os = "ios"));
+    #[cfg(not(feature = "qos"))]
+    let has_qos = false;
+
+    #[cfg(feature = "qos")]
+    if let Some(class) = config.qos_class {
+        if let Err(error) = os::set_qos_class(class) {
+            config.warn(Warning::QosClass { worker: idx, error });
+        }
+    }
+
+    #[cfg(feature = "priority")]
+    if let Some(priority) = config.os_priority.filter(|_| !has_qos) {
+        if let Err(error) = os::set_priority(priority) {
+            config.warn(Warning::Priority { worker: idx, error });
+        }
+    }
+
+    #[cfg(feature = "ioprio")]
+    if let Some(priority) = config.io_priority {
+        match os::set_io_priority(priority) {
+            Ok(()) => { shared.io_priority_applied.fetch_add(1, Ordering::Relaxed); },
+            Err(error) => config.warn(Warning::IoPriority { worker: idx, error }),
+        }
+    }
+
+    #[cfg(feature = "numa")]
+    if !shared.numa_nodes.is_empty() {
+        let (node, cpus) = &shared.numa_nodes[idx % shared.numa_nodes.len()];
+        match os::set_cpu_affinity(cpus) {
+            Ok(()) => shared.numa_placed.lock().unwrap()[idx] = Some(*node),
+            Err(error) => config.warn(Warning::NumaNode { worker: idx, node: *node, error }),
+        }
+    }
+
+    #[cfg(all(unix, feature = "realtime"))]
+    if let Some(policy) = config.realtime_policy {
+        let res = os::set_realtime_policy(policy);
+        if let Err(error) = &res {
+            config.warn(Warning::RealtimePolicy { worker: idx, error: os::copy_error(error) });
+        }
+        shared.realtime_results.lock().unwrap()[idx] = Some(res);
+        shared.realtime_reported.notify_all();
+    }
+}
+
+// Touch the given number of bytes of the current thread's stack, one page-sized chunk per frame
+#[inline(never)]
+fn prefault_stack(bytes: usize) {
+    const CHUNK: usize = 4096;
+    let mut chunk = [0u8; CHUNK];
+    // Prevent the chunk (and hence the recursion) from being optimised away
+    std::hint::black_box(&mut chunk);
+    if bytes > CHUNK {
+        prefault_stack(bytes - CHUNK);
+    }
+    std::hint::black_box(&chunk);
+}
+
+// Run a hook, catching any panic that occurs. The panic will still be reported by the panic hook as normal, but it
+// won't take down the worker.
+fn run_hook(hook: &Option<WorkerHook>, idx: usize) {
+    if let Some(hook) = hook {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| hook(idx)));
+    }
+}